@@ -0,0 +1,23 @@
+#![allow(dead_code)]
+
+use residency_match::driver::generate_population_pool_;
+use residency_match::models::{generator, Applicant, Couple, Program};
+
+/// Every benchmark samples its population from this seed, so runs are comparable.
+pub const SEED: u64 = 0x5eed;
+
+pub const SCALES: [usize; 3] = [1000, 10000, 50000];
+
+/// Couple rates compared at `COUPLE_RATE_SCALE` applicants.
+pub const COUPLE_RATES: [f64; 3] = [0.0, 0.02, 0.1];
+pub const COUPLE_RATE_SCALE: usize = 10000;
+
+/// Keeps the default ratio of 50,000 applicants to 10,000 programs.
+pub fn num_programs(num_applicants: usize) -> usize {
+    num_applicants / 5
+}
+
+pub fn population(num_applicants: usize, couple_rate: f64) -> (Vec<Couple<Applicant>>, Vec<Program>) {
+    generator::seed(SEED);
+    generate_population_pool_(num_applicants, num_programs(num_applicants), couple_rate)
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use residency_match::driver::generate_population_pool_;
+use residency_match::models::generator;
+use common::{num_programs, COUPLE_RATES, COUPLE_RATE_SCALE, SCALES, SEED};
+
+fn population_pool(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_population_pool");
+    group.sample_size(10);
+    for n in SCALES {
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| b.iter(|| {
+            generator::seed(SEED);
+            generate_population_pool_(n, num_programs(n), generator::COUPLE_RATE)
+        }));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("generate_population_pool_couple_rate");
+    group.sample_size(10);
+    for rate in COUPLE_RATES {
+        group.bench_with_input(BenchmarkId::from_parameter(rate), &rate, |b, &rate| b.iter(|| {
+            generator::seed(SEED);
+            generate_population_pool_(COUPLE_RATE_SCALE, num_programs(COUPLE_RATE_SCALE), rate)
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, population_pool);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use criterion::{criterion_group, criterion_main, Criterion};
+use residency_match::driver::generate_population_pool;
+use residency_match::models::{Applicant, Program};
+use residency_match::ranker::{self, ProgramRankPolicy, RankingStrategy, ReceiveApplication, TieredRanking};
+
+const NUM_APPLICANTS: usize = 50000;
+const NUM_PROGRAMS: usize = 10000;
+
+/// Every program of the default configuration (without its applications) alongside the
+/// applicants that apply to it, in the order they apply.
+fn received_applications<'a>(applicants: &'a HashMap<u32, Applicant>, programs: &[Program]) -> Vec<(Program, Vec<&'a Applicant>)> {
+    programs.iter().map(|p| {
+        let received = p.applications.iter().map(|a| &applicants[&a.applicant]).collect();
+        (Program { applications: Vec::new(), ..p.clone() }, received)
+    }).collect()
+}
+
+/// The intake this replaced: the whole list of applications was re-sorted on every application.
+fn sort_on_receive(program: &Program, received: &[&Applicant], policy: &ProgramRankPolicy) -> Vec<u32> {
+    let mut applications: Vec<(u32, f32)> = Vec::new();
+    for a in received {
+        applications.push((a.id, a.competitiveness));
+        applications.sort_by(|(_, comp_a), (_, comp_b)| comp_b.total_cmp(comp_a));
+    }
+    applications.sort_by(|(_, comp_a), (_, comp_b)| comp_b.total_cmp(comp_a));
+    applications.iter()
+        .take(policy.depth as usize * program.capacity as usize)
+        .map(|(a, _)| *a)
+        .collect()
+}
+
+fn intake(c: &mut Criterion) {
+    let strategy = TieredRanking::default();
+    let (mut population, mut programs) = generate_population_pool(NUM_APPLICANTS, NUM_PROGRAMS);
+    RankingStrategy::<Applicant, Program>::prepare(&strategy, &mut programs);
+    let index = ranker::program_index::<Applicant, Program>(&programs);
+    for c in population.iter_mut() {
+        let plan = strategy.plan_applicant(c, &programs);
+        strategy.submit_plan(c, plan, &mut programs, &index);
+    }
+    let applicants: HashMap<u32, Applicant> = population.into_iter()
+        .flat_map(|c| std::iter::once(c.0).chain(c.1))
+        .map(|a| (a.id, a))
+        .collect();
+    let received = received_applications(&applicants, &programs);
+
+    let mut group = c.benchmark_group("intake_50000x10000");
+    group.sample_size(10);
+    group.bench_function("sort_on_receive", |b| b.iter(|| {
+        received.iter()
+            .map(|(p, r)| sort_on_receive(p, r, &strategy.program_policy).len())
+            .sum::<usize>()
+    }));
+    group.bench_function("batched", |b| b.iter(|| {
+        received.iter().map(|(p, r)| {
+            let mut p = p.clone();
+            for a in r {
+                p.receive_application(a, false);
+            }
+            p.process_applications(&strategy.program_policy);
+            p.ranking.len()
+        }).sum::<usize>()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, intake);
+criterion_main!(benches);
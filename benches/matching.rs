@@ -0,0 +1,45 @@
+//! `run_match` on 50,000 applicants takes minutes per iteration, so the full suite takes
+//! most of an hour; filter it out with e.g. `cargo bench --bench matching -- '/(1000|10000)$'`.
+
+mod common;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use residency_match::driver::generate_rankings;
+use residency_match::matcher::Matcher;
+use residency_match::models::{generator, Applicant, Couple, Program};
+use common::{population, COUPLE_RATES, COUPLE_RATE_SCALE, SCALES};
+
+fn ranked_population(num_applicants: usize, couple_rate: f64) -> (Vec<Couple<Applicant>>, Vec<Program>) {
+    let (mut applicants, mut programs) = population(num_applicants, couple_rate);
+    generate_rankings(&mut applicants, &mut programs);
+    (applicants, programs)
+}
+
+fn run_match(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run_match");
+    group.sample_size(10);
+    for n in SCALES {
+        let (applicants, programs) = ranked_population(n, generator::COUPLE_RATE);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, _| b.iter(|| {
+            let mut matcher = Matcher::new();
+            matcher.run_match(&applicants, &programs).unwrap();
+            matcher.unmatched_a.len()
+        }));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("run_match_couple_rate");
+    group.sample_size(10);
+    for rate in COUPLE_RATES {
+        let (applicants, programs) = ranked_population(COUPLE_RATE_SCALE, rate);
+        group.bench_with_input(BenchmarkId::from_parameter(rate), &rate, |b, _| b.iter(|| {
+            let mut matcher = Matcher::new();
+            matcher.run_match(&applicants, &programs).unwrap();
+            matcher.unmatched_a.len()
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, run_match);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use residency_match::driver::{generate_naive_rankings, generate_rankings};
+use residency_match::models::{generator, Applicant, Couple, Program};
+use common::{population, COUPLE_RATES, COUPLE_RATE_SCALE, SCALES};
+
+fn bench_rankings<F>(c: &mut Criterion, name: &str, rank: F)
+where F: Fn(&mut Vec<Couple<Applicant>>, &mut Vec<Program>)
+{
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+    for n in SCALES {
+        let pool = population(n, generator::COUPLE_RATE);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &pool, |b, pool| b.iter_batched(
+            || pool.clone(),
+            |(mut applicants, mut programs)| rank(&mut applicants, &mut programs),
+            BatchSize::LargeInput
+        ));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("{}_couple_rate", name));
+    group.sample_size(10);
+    for rate in COUPLE_RATES {
+        let pool = population(COUPLE_RATE_SCALE, rate);
+        group.bench_with_input(BenchmarkId::from_parameter(rate), &pool, |b, pool| b.iter_batched(
+            || pool.clone(),
+            |(mut applicants, mut programs)| rank(&mut applicants, &mut programs),
+            BatchSize::LargeInput
+        ));
+    }
+    group.finish();
+}
+
+fn rankings(c: &mut Criterion) {
+    bench_rankings(c, "generate_rankings", |a, p| generate_rankings(a, p));
+}
+
+fn naive_rankings(c: &mut Criterion) {
+    bench_rankings(c, "generate_naive_rankings", |a, p| generate_naive_rankings(a, p));
+}
+
+criterion_group!(benches, rankings, naive_rankings);
+criterion_main!(benches);
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::io::{BufWriter, Read, Write};
+use serde::{Deserialize, Serialize};
+use crate::matcher::Matcher;
+use crate::models::{Couple, HasCapacity, HasCouple, HasSupplemental};
+use crate::ranker::Rankable;
+use crate::stability;
+
+/// Event files start with this, followed by the format version.
+const MAGIC: [u8; 4] = *b"RMEV";
+const VERSION: u32 = 1;
+
+/// An applicant (or one partner of a couple) asking a program for a place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub applicant: u32,
+    pub program: u32,
+    /// For a partner of a couple, the program their partner asked for at the same time.
+    pub paired_with: Option<u32>,
+    /// Whether this is for a preliminary position, from a supplemental rank list.
+    pub linked: bool,
+}
+
+/// Why a program turned an applicant down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rejection {
+    NotRanked,
+    NoPositions,
+    /// Every place was held by someone the program ranked higher. Ranks count from 0.
+    Full { rank: usize, weakest_rank: usize },
+    /// The program would have taken the applicant, but their partner's program didn't take
+    /// their partner.
+    PartnerRejected,
+}
+
+/// Something that happened in the match, in the order it happened. Every proposal is either
+/// accepted or rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchEvent {
+    /// The program tentatively took the applicant, whom it ranked at `rank`.
+    Accepted { proposal: Proposal, rank: usize },
+    Rejected { proposal: Proposal, reason: Rejection },
+    /// The applicant lost their tentative place at the program to `by`, whom it ranked higher.
+    Displaced { applicant: u32, program: u32, by: u32, rank: usize, by_rank: usize },
+    /// The applicant gave up their tentative place at the program, because their partner lost
+    /// theirs (or, for a preliminary position, because they lost their advanced program).
+    Withdrawn { applicant: u32, program: u32 },
+    /// The couple kept displacing (and being displaced by) other couples, and was left unmatched.
+    GaveUp { applicant: u32 },
+    /// The applicant ran out of programs on their rank list, and was left unmatched.
+    Exhausted { applicant: u32 },
+    /// The applicant was put back into the match to try again, because they were in a blocking pair.
+    Reentered { applicant: u32 },
+}
+
+/// Somewhere to send a match's events as they happen.
+pub trait EventSink {
+    fn record(&mut self, event: &MatchEvent);
+    /// Called once the match is over, for sinks that write somewhere that can fail.
+    fn finish(&mut self) -> bincode::Result<()> {
+        Ok(())
+    }
+}
+
+impl EventSink for Vec<MatchEvent> {
+    fn record(&mut self, event: &MatchEvent) {
+        self.push(event.clone());
+    }
+}
+
+/// Writes events to a file as they happen, to be read back with `read_events`. Writing
+/// stops at the first error, which `finish` returns.
+pub struct EventFile {
+    writer: BufWriter<std::fs::File>,
+    error: Option<bincode::Error>,
+}
+
+impl EventFile {
+    pub fn create(path: &str) -> bincode::Result<EventFile> {
+        let mut writer = BufWriter::new(std::fs::File::create(path)?);
+        writer.write_all(&MAGIC)?;
+        bincode::serialize_into(&mut writer, &VERSION)?;
+        Ok(EventFile { writer, error: None })
+    }
+}
+
+impl EventSink for EventFile {
+    fn record(&mut self, event: &MatchEvent) {
+        if self.error.is_none() {
+            self.error = bincode::serialize_into(&mut self.writer, event).err();
+        }
+    }
+
+    fn finish(&mut self) -> bincode::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(self.writer.flush()?)
+        }
+    }
+}
+
+/// Reads the events an `EventFile` wrote, in order.
+pub fn read_events(path: &str) -> bincode::Result<Vec<MatchEvent>> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!("{} is not an event file", path))));
+    }
+    match bincode::deserialize_from::<_, u32>(&mut reader)? {
+        VERSION => {},
+        version => return Err(Box::new(bincode::ErrorKind::Custom(format!("unsupported event file version {}", version))))
+    }
+    let mut events = Vec::new();
+    loop {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(event) => events.push(event),
+            Err(e) => match *e {
+                bincode::ErrorKind::Io(ref io) if io.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(events),
+                _ => return Err(e)
+            }
+        }
+    }
+}
+
+/// Where a match's events go: kept in memory (see `Matcher::record_events`), sent to a sink
+/// (see `Matcher::stream_events`), both or neither. Clones keep the events recorded so far,
+/// but not the sink.
+#[derive(Default)]
+pub struct EventLog {
+    recorded: Option<Vec<MatchEvent>>,
+    sink: Option<Box<dyn EventSink>>,
+}
+
+impl Clone for EventLog {
+    fn clone(&self) -> Self {
+        EventLog { recorded: self.recorded.clone(), sink: None }
+    }
+}
+
+impl EventLog {
+    pub fn is_on(&self) -> bool {
+        self.recorded.is_some() || self.sink.is_some()
+    }
+
+    /// The events kept in memory, if they are being kept.
+    pub fn recorded(&self) -> Option<&[MatchEvent]> {
+        self.recorded.as_deref()
+    }
+
+    pub fn keep(&mut self) {
+        self.recorded = Some(Vec::new());
+    }
+
+    pub fn send_to(&mut self, sink: Box<dyn EventSink>) {
+        self.sink = Some(sink);
+    }
+
+    /// Adds an event to the log. `event` is only called if the log is on.
+    pub fn record(&mut self, event: impl FnOnce() -> MatchEvent) {
+        if !self.is_on() {
+            return;
+        }
+        let event = event();
+        if let Some(sink) = self.sink.as_mut() {
+            sink.record(&event);
+        }
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.push(event);
+        }
+    }
+
+    /// Forgets the events kept in memory. Events already sent to the sink stay sent.
+    pub fn clear(&mut self) {
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.clear();
+        }
+    }
+
+    /// Finishes with the sink, if there is one, and stops sending events to it.
+    pub fn finish(&mut self) -> bincode::Result<()> {
+        match self.sink.take() {
+            None => Ok(()),
+            Some(mut sink) => sink.finish()
+        }
+    }
+}
+
+/// What became of one entry on an applicant's rank list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Matched,
+    /// The applicant never got this far down their list, having been placed higher up.
+    NotReached,
+    Rejected(Rejection),
+    Displaced { by: u32, rank: usize, by_rank: usize },
+    Withdrawn,
+    GaveUp,
+}
+
+/// An entry on an applicant's rank list and what became of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub program: u32,
+    /// For a partner of a couple, their partner's program in the same entry of the joint rank list.
+    pub paired_with: Option<u32>,
+    pub outcome: Outcome,
+}
+
+/// Why an applicant was or wasn't placed at each program on their rank list.
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub applicant: u32,
+    pub matched: Option<u32>,
+    pub choices: Vec<Choice>,
+}
+
+/// Reconstructs, from the events a match recorded, what became of each entry on an
+/// applicant's rank list. The last thing that happened at an entry is what explains it, and
+/// an applicant put back into the match is explained by how they went through it the last
+/// time. `None` if the applicant isn't in `applicants` or the match recorded no events.
+pub fn explain<A, P>(applicants: &[Couple<A>], matcher: &Matcher<A, P>, applicant: u32) -> Option<Explanation>
+where A: Rankable<P> + HasCouple + HasSupplemental + Clone,
+      P: Rankable<A> + HasCapacity
+{
+    let events = matcher.events.recorded()?;
+    let (a, partner) = applicants.iter().find_map(|c| match (&c.0, &c.1) {
+        (a, b) if a.id() == applicant => Some((a, b.as_ref())),
+        (a, Some(b)) if b.id() == applicant => Some((b, Some(a))),
+        _ => None
+    })?;
+    let ranking = a.ranking();
+    let paired: Vec<Option<u32>> = match partner {
+        None => vec![None; ranking.len()],
+        Some(b) => b.ranking().into_iter().map(Some).collect()
+    };
+
+    let mut last: HashMap<(u32, Option<u32>), Outcome> = HashMap::new();
+    let mut held: Option<(u32, Option<u32>)> = None;
+    for event in events {
+        match event {
+            MatchEvent::Accepted { proposal, .. } if proposal.applicant == applicant && !proposal.linked => {
+                held = Some((proposal.program, proposal.paired_with));
+                last.insert((proposal.program, proposal.paired_with), Outcome::Matched);
+            },
+            MatchEvent::Rejected { proposal, reason } if proposal.applicant == applicant && !proposal.linked => {
+                last.insert((proposal.program, proposal.paired_with), Outcome::Rejected(*reason));
+            },
+            MatchEvent::Displaced { applicant: d, program, by, rank, by_rank } if *d == applicant => {
+                if let Some(entry) = held.filter(|(p, _)| p == program) {
+                    last.insert(entry, Outcome::Displaced { by: *by, rank: *rank, by_rank: *by_rank });
+                    held = None;
+                }
+            },
+            MatchEvent::Withdrawn { applicant: w, program } if *w == applicant => {
+                if let Some(entry) = held.filter(|(p, _)| p == program) {
+                    last.insert(entry, Outcome::Withdrawn);
+                    held = None;
+                }
+            },
+            MatchEvent::GaveUp { applicant: g } if *g == applicant || partner.is_some_and(|b| b.id() == *g) => {
+                for p in ranking.iter().zip(paired.iter()) {
+                    last.entry((*p.0, *p.1)).or_insert(Outcome::GaveUp);
+                }
+            },
+            MatchEvent::Reentered { applicant: r } if *r == applicant || partner.is_some_and(|b| b.id() == *r) => {
+                last.clear();
+                held = None;
+            },
+            _ => {}
+        }
+    }
+
+    let assigned = stability::assignment(matcher);
+    let matched = assigned.get(&applicant).copied();
+    let partner_matched = partner.and_then(|b| assigned.get(&b.id()).copied());
+    let choices = ranking.iter().zip(paired).map(|(program, paired_with)| {
+        let outcome = match matched == Some(*program) && paired_with == partner_matched {
+            true => Outcome::Matched,
+            false => match last.get(&(*program, paired_with)) {
+                // never proposed to, or taken but not held at the end
+                None | Some(Outcome::Matched) => Outcome::NotReached,
+                Some(outcome) => outcome.clone()
+            }
+        };
+        Choice { program: *program, paired_with, outcome }
+    }).collect();
+
+    Some(Explanation { applicant, matched, choices })
+}
@@ -1,11 +1,25 @@
-use crate::matcher::Matcher;
+use crate::audit::{self, EventFile, Outcome, Rejection};
+use crate::lattice::StableLattice;
+use crate::matcher::{InstabilityResolution, Matcher};
 use crate::parameters::MatchParameters;
-use crate::models::{Applicant, Couple, HasCouple, Program};
-use crate::ranker::{RankDistribution, RankStrategy, Rankable, ReceiveApplication};
-use std::io::{Write, stdout};
+use crate::models::{generator, Applicant, Couple, HasCouple, HasPositionType, HasProfile, MatchStatus, PositionType, Program, Stratification};
+use crate::ranker::{NaiveRanking, Rankable, RankingStrategy, TieredRanking};
+use crate::ranker;
+use crate::replay::Replayer;
+use crate::lifecycle;
+use crate::manipulation::{self, Manipulation, ManipulationPolicy};
+use crate::outcome::{self, Direction, MatchOutcome, OutcomeDiff};
+use crate::scenario::{self, OutcomeSummary, Scenario};
+use crate::soap;
+use crate::stability;
+use crate::timeline;
+use crate::ties::{self, TieBreaking};
+use crate::tracks;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::io::{Stdout, Write, stdout};
 use crossterm::{QueueableCommand, cursor, terminal, ExecutableCommand};
 use std::time::Instant;
-use crate::ranker;
 
 trait AsMinutes {
     fn as_minutes(&self) -> f32;
@@ -17,19 +31,17 @@ impl AsMinutes for std::time::Duration {
 }
 
 pub fn generate_match_parameters(num_applicants: usize, num_programs: usize) -> MatchParameters {
-    generate_match_parameters_(num_applicants, num_programs, false)
+    generate_match_parameters_(num_applicants, num_programs, &TieredRanking::default())
 }
 
-pub fn generate_match_parameters_(num_applicants: usize, num_programs: usize, rank_naive: bool) -> MatchParameters {
+pub fn generate_match_parameters_<S>(num_applicants: usize, num_programs: usize, strategy: &S) -> MatchParameters
+where S: RankingStrategy<Applicant, Program>
+{
     let (mut applicants, mut programs) = generate_population_pool(num_applicants, num_programs);
     let num_applicants = num_applicants + applicants.iter()
         .filter(|a| a.1.is_some()).collect::<Vec<_>>().len();
 
-    if rank_naive {
-        generate_naive_rankings(&mut applicants, &mut programs);
-    } else {
-        generate_rankings(&mut applicants, &mut programs);
-    }
+    generate_rankings_(&mut applicants, &mut programs, strategy);
 
     MatchParameters {
         num_applicants,
@@ -40,10 +52,14 @@ pub fn generate_match_parameters_(num_applicants: usize, num_programs: usize, ra
 }
 
 pub fn generate_population_pool(num_applicants: usize, num_programs: usize) -> (Vec<Couple<Applicant>>, Vec<Program>) {
+    generate_population_pool_(num_applicants, num_programs, generator::COUPLE_RATE)
+}
+
+pub fn generate_population_pool_(num_applicants: usize, num_programs: usize, couple_rate: f64) -> (Vec<Couple<Applicant>>, Vec<Program>) {
     let start = Instant::now();
 
     let applicants: Vec<Couple<Applicant>> = (0..num_applicants)
-        .map(|_| Applicant::sample_applicant().into())
+        .map(|_| Applicant::sample_applicant_(couple_rate).into())
         .collect();
     let programs: Vec<Program> = (0..num_programs)
         .map(|_| Program::sample_program())
@@ -54,88 +70,90 @@ pub fn generate_population_pool(num_applicants: usize, num_programs: usize) -> (
     (applicants, programs)
 }
 
-pub fn generate_naive_rankings(applicants: &mut Vec<Couple<Applicant>>, programs: &mut Vec<Program>) {
-    let start = Instant::now();
-
-    let mut all_programs = programs.iter().collect::<Vec<_>>();
-    animated_process(applicants,
-                     |i, len|
-                         format!("...Ranked {}/{} applicants ({:.0}%)...",
-                                 i, len, i as f64 / len as f64 * 100.0),
-                     |c| {
-                         c.0.naive_rank(&mut all_programs, c.0.applications);
-                         if let Some(couple) = c.1.as_mut() {
-                             couple.ranking = c.0.ranking.clone();
-                         }
-                     }
-    );
-
-    let mut all_applicants = applicants.iter().flat_map(|c| match &c.1 {
-        Some(couple) => vec![&c.0, &couple],
-        None => vec![&c.0]
-    }).collect::<Vec<_>>();
-    animated_process(programs,
-                     |i, len|
-                         format!("...Ranked {}/{} programs ({:.0}%)...",
-                                 i, len, i as f64 / len as f64 * 100.0),
-                     |program| {
-                         program.naive_rank(
-                             &mut all_applicants,
-                             program.capacity * 15
-                         );
-                     }
-    );
+pub fn generate_naive_rankings(applicants: &mut [Couple<Applicant>], programs: &mut Vec<Program>) {
+    generate_rankings_(applicants, programs, &NaiveRanking)
+}
 
-    println!("Built rankings in {:.2?}.", start.elapsed());
+pub fn generate_rankings(applicants: &mut [Couple<Applicant>], programs: &mut Vec<Program>) {
+    generate_rankings_(applicants, programs, &TieredRanking::default())
 }
 
-pub fn generate_rankings(applicants: &mut Vec<Couple<Applicant>>, programs: &mut Vec<Program>) {
+pub fn generate_rankings_<S>(applicants: &mut [Couple<Applicant>], programs: &mut Vec<Program>, strategy: &S)
+where S: RankingStrategy<Applicant, Program>
+{
     let start = Instant::now();
 
-    let strategy = RankStrategy {
-        reach_multiplier: 1.05,
-        realistic_multiplier: 0.95,
-        safety_multiplier: 0.90
-    };
-    let distribution = RankDistribution {
-        reach: 0.3,
-        realistic: 0.5,
-        safety: 0.2
-    };
-
-    programs.sort_by(|a, b| b.competitiveness.total_cmp(&a.competitiveness));
-    animated_process(applicants,
-                     |i, len|
-                         format!("...Ranked {}/{} applicants ({:.0}%)...",
-                                 i, len, i as f64 / len as f64 * 100.0),
-                     |c|
-                         ranker::rank(c, programs, &strategy, &distribution)
+    strategy.prepare(programs);
+    let plans = animated_par_map(applicants,
+                                 |i, len|
+                                     format!("...Planned {}/{} applicants ({:.0}%)...",
+                                             i, len, i as f64 / len as f64 * 100.0),
+                                 |c|
+                                     strategy.plan_applicant(c, programs)
     );
 
-    animated_process(programs,
-                     |i, len|
+    // applications are handed to programs in applicant order, so the result is the same
+    // however the plans were scheduled
+    let index = ranker::program_index::<Applicant, Program>(programs);
+    let mut truncated = 0usize;
+    let mut shortfall = 0usize;
+    for (c, plan) in applicants.iter_mut().zip(plans) {
+        let short = strategy.submit_plan(c, plan, programs, &index);
+        if short > 0 {
+            truncated += 1;
+            shortfall += short;
+        }
+    }
 
-                         format!("...Ranked {}/{} applicants ({:.0}%)...",
-                                 i, len, i as f64 / len as f64 * 100.0),
-                     |p|
-                         p.process_applications()
+    let all_applicants = applicants.iter().flat_map(|c| match &c.1 {
+        Some(couple) => vec![&c.0, &couple],
+        None => vec![&c.0]
+    }).collect::<Vec<_>>();
+    animated_par_process(programs,
+                         |i, len|
+                             format!("...Ranked {}/{} programs ({:.0}%)...",
+                                     i, len, i as f64 / len as f64 * 100.0),
+                         |p|
+                             strategy.rank_program(p, &all_applicants)
     );
 
     println!("Built rankings in {:.2?}min.", start.elapsed().as_minutes());
+    if truncated > 0 {
+        println!("{} applicants ({:.1}%) have truncated rank lists, {} applications short in total.",
+                 truncated, truncated as f32 / applicants.len() as f32 * 100.0, shortfall);
+    }
+
+    lifecycle::record_applications(applicants, programs);
 }
 
-pub fn run_simulation(parameters: MatchParameters) {
+pub fn run_simulation(mut parameters: MatchParameters) {
+    report_excluded(&timeline::exclude_late(&mut parameters));
+    lifecycle::certify(&mut parameters.applicants);
+    if parameters.programs.iter().any(|p| !p.tiers.is_empty()) {
+        report_tie_breaking(&parameters, 0);
+        ties::break_ties(&mut parameters, TieBreaking::default(), 0);
+    }
+
     let MatchParameters {
         num_applicants,
-        num_programs,
-        applicants,
-        programs
+        mut num_programs,
+        mut applicants,
+        mut programs
     } = parameters;
 
     let start = Instant::now();
 
+    // programs with tracks are matched track by track, with the positions reversion moved
+    if programs.iter().any(|p| !p.tracks.is_empty()) {
+        programs = match revert_tracks(&applicants, &programs) {
+            Some(tracks) => tracks,
+            None => return
+        };
+        num_programs = programs.len();
+    }
+
     let mut matcher = Matcher::new();
-    match matcher.run_match(&applicants, &programs) {
+    match matcher.run_match_(&applicants, &programs, InstabilityResolution::Reprocess) {
         Err(ref e) => {
             eprintln!("Error while matching: {:?}", e.to_string());
             return
@@ -143,12 +161,31 @@ pub fn run_simulation(parameters: MatchParameters) {
         Ok(_) => println!("Finished match in {:.2?}min.", start.elapsed().as_minutes())
     };
 
+    let instability = &matcher.instability;
+    if instability.is_stable() {
+        println!("Match is stable after {} rounds of reprocessing.", instability.rounds);
+        if !instability.cycling_couples.is_empty() {
+            let couples = &instability.cycling_couples;
+            println!("{} couples kept displacing other couples until left unmatched: {:?}{}",
+                     couples.len(), &couples[..usize::min(couples.len(), 10)], if couples.len() > 10 { " ..." } else { "" });
+        }
+    } else {
+        let couples = instability.unstable_couples();
+        println!("Match is unstable: {} blocking pairs remain after {} rounds of reprocessing{}.",
+                 instability.blocking_pairs.len(), instability.rounds,
+                 if instability.repeated { " (the match was cycling)" } else { "" });
+        println!("{} couples are involved, {} of which kept displacing other couples until left unmatched: {:?}{}",
+                 couples.len(), instability.cycling_couples.len(),
+                 &couples[..usize::min(couples.len(), 10)], if couples.len() > 10 { " ..." } else { "" });
+    }
+
     let matched_programs = matcher.matches.len();
     let unfilled_positions = matcher.unfilled_positions();
+    // applicants holding a preliminary position alongside an advanced one are in two programs
     let matched_applicants = matcher.matches.iter()
         .flat_map(|m| &m.1)
         .collect::<Vec<&&Applicant>>()
-        .len();
+        .len() - matcher.linked.len();
     let unmatched_applicants = matcher.unmatched_a.len();
     let matched_couples = matcher.matches.iter().flat_map(|m| &m.1).filter(|a| a.get_couple().is_some()).collect::<Vec<_>>().len();
     let unmatched_couples = matcher.unmatched_a.iter().filter(|a| a.get_couple().is_some()).collect::<Vec<_>>().len();
@@ -166,6 +203,16 @@ pub fn run_simulation(parameters: MatchParameters) {
              unmatched_couples, unmatched_couples as f32 / (matched_couples + unmatched_couples) as f32 * 100.0,
     );
 
+    let advanced = matcher.matches.iter()
+        .filter(|m| m.0.position_type() == PositionType::Advanced)
+        .flat_map(|m| &m.1)
+        .collect::<Vec<_>>();
+    if !advanced.is_empty() {
+        let without = advanced.iter().filter(|a| !matcher.linked.contains_key(&a.id())).count();
+        println!("Matched to advanced programs: {}, {} of which ({:.1}%) matched no preliminary position",
+                 advanced.len(), without, without as f32 / advanced.len() as f32 * 100.0);
+    }
+
     let first_choicers = matcher.matches.iter()
         .filter(|m| m.1.iter().any(|a| a.ranking[0] == m.0.id()))
         .flat_map(|m| &m.1)
@@ -185,6 +232,11 @@ pub fn run_simulation(parameters: MatchParameters) {
              couples_first_choice, couples_first_choice as f32 / matched_couples as f32 * 100.0
     );
 
+    report_strata(&matcher, Stratification::School);
+    report_strata(&matcher, Stratification::Visa);
+
+    report_soap(&matcher);
+
     println!();
 
     let sample_match = matcher.matches.iter().find(|m| m.1.len() > 0).unwrap();
@@ -201,6 +253,378 @@ pub fn run_simulation(parameters: MatchParameters) {
     sample_match.1.iter().for_each(|a| {
         println!("{} (#{})", a.id(), sample_program.ranking.iter().position(|i| *i == a.id()).unwrap() + 1);
     });
+
+    let assigned = stability::assignment(&matcher);
+    lifecycle::record_outcome(&mut applicants, &assigned);
+    println!();
+    report_statuses(&applicants);
+}
+
+/// Reports how many applicants ended the match at each status, which for applicants who
+/// didn't go into the match is the stage they fell out at.
+pub fn report_statuses(applicants: &[Couple<Applicant>]) {
+    let statuses = lifecycle::by_status(applicants);
+    let total: usize = statuses.values().map(|a| a.len()).sum();
+    println!("Applicants by match status:");
+    for (status, ids) in statuses.iter() {
+        let fell_out = if *status < MatchStatus::Certified { " (fell out before the match)" } else { "" };
+        println!("  {:?}: {} ({:.1}%){}", status, ids.len(), ids.len() as f32 / total as f32 * 100.0, fell_out);
+    }
+}
+
+/// Gets parameters ready to match as `run_simulation` does, without reporting on it.
+fn prepare_match(parameters: &mut MatchParameters) {
+    timeline::exclude_late(parameters);
+    lifecycle::certify(&mut parameters.applicants);
+    ties::break_ties(parameters, TieBreaking::default(), 0);
+}
+
+/// Runs the match as `run_simulation` does, writing every event to `path` as it happens.
+pub fn save_match_events(mut parameters: MatchParameters, path: &str) {
+    prepare_match(&mut parameters);
+    let start = Instant::now();
+    let mut matcher = Matcher::new();
+    match EventFile::create(path) {
+        Err(ref e) => return eprintln!("Error while creating {}: {:?}", path, e.to_string()),
+        Ok(file) => matcher.stream_events(file)
+    }
+    if let Err(ref e) = matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess) {
+        return eprintln!("Error while matching: {:?}", e.to_string());
+    }
+    match matcher.finish_events() {
+        Err(ref e) => eprintln!("Error while writing {}: {:?}", path, e.to_string()),
+        Ok(_) => println!("Saved match events to {} in {:.2?}.", path, start.elapsed())
+    }
+}
+
+/// Replays the first `position` events saved by `save_match_events`, and reports the last
+/// few of them and where the match stood after them.
+pub fn report_replay(path: &str, position: usize) {
+    let events = match audit::read_events(path) {
+        Err(ref e) => return eprintln!("Error while reading {}: {:?}", path, e.to_string()),
+        Ok(events) => events
+    };
+    let mut replayer = Replayer::new(&events);
+    let position = usize::min(position, events.len());
+    replayer.seek(position.saturating_sub(10));
+    while replayer.position() < position {
+        let i = replayer.position();
+        println!("  {}: {:?}", i + 1, replayer.step().unwrap());
+    }
+    let state = replayer.state();
+    println!("After {} of {} events: {} applicants hold places at {} programs ({} of them preliminary positions), {} unmatched.",
+             position, events.len(), state.holds.values().map(|h| h.len()).sum::<usize>() - state.linked.len(),
+             state.holds.len(), state.linked.len(), state.unmatched.len());
+}
+
+/// Runs the match as `run_simulation` does, recording what happened, and reports why the
+/// applicant was or wasn't placed at each program on their rank list.
+pub fn report_explanation(mut parameters: MatchParameters, applicant: u32) {
+    prepare_match(&mut parameters);
+    let mut matcher = Matcher::new();
+    matcher.record_events();
+    if let Err(ref e) = matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess) {
+        eprintln!("Error while matching: {:?}", e.to_string());
+        return
+    }
+    let explanation = match audit::explain(&parameters.applicants, &matcher, applicant) {
+        None => {
+            eprintln!("Applicant {} is not in the match.", applicant);
+            return
+        },
+        Some(explanation) => explanation
+    };
+    let name = |id: u32| match parameters.programs.iter().find(|p| p.id == id) {
+        None => format!("Program {}", id),
+        Some(p) => format!("{} ({})", p.name, id)
+    };
+
+    match explanation.matched {
+        None => println!("Applicant {} did not match.", applicant),
+        Some(program) => println!("Applicant {} matched to {}.", applicant, name(program))
+    }
+    for (i, choice) in explanation.choices.iter().enumerate() {
+        let partner = match choice.paired_with {
+            None => String::new(),
+            Some(q) => format!(", with their partner at {}", name(q))
+        };
+        let outcome = match &choice.outcome {
+            Outcome::Matched => "matched".to_string(),
+            Outcome::NotReached => "not reached, having matched higher up the rank list".to_string(),
+            Outcome::Rejected(Rejection::NotRanked) => "the program did not rank them".to_string(),
+            Outcome::Rejected(Rejection::NoPositions) => "the program had no positions".to_string(),
+            Outcome::Rejected(Rejection::Full { rank, weakest_rank }) =>
+                format!("the program was full, ranking them #{} and its weakest match #{}", rank + 1, weakest_rank + 1),
+            Outcome::Rejected(Rejection::PartnerRejected) => "their partner's program did not take their partner".to_string(),
+            Outcome::Displaced { by, rank, by_rank } =>
+                format!("displaced by applicant {}, whom the program ranked #{} to their #{}", by, by_rank + 1, rank + 1),
+            Outcome::Withdrawn => "withdrawn when their partner was displaced".to_string(),
+            Outcome::GaveUp => "left unmatched after displacing (and being displaced by) other couples".to_string()
+        };
+        println!("  #{} {}{}: {}", i + 1, name(choice.program), partner, outcome);
+    }
+}
+
+/// Tries manipulations for a sample of applicants and programs, and reports how often
+/// each kind paid off and by how much.
+pub fn report_manipulation(parameters: &MatchParameters, policy: &ManipulationPolicy, seed: u64) {
+    let start = Instant::now();
+    let outcomes = match manipulation::analyze_manipulations(parameters, policy, seed) {
+        Err(ref e) => {
+            eprintln!("Error while trying manipulations: {:?}", e.to_string());
+            return
+        },
+        Ok(outcomes) => outcomes
+    };
+    println!("Tried {} manipulations in {:.2?}.", outcomes.len(), start.elapsed());
+    let kinds = ["Truncation", "Swap", "Withholding", "ProgramTruncation"];
+    for kind in kinds {
+        let tried: Vec<_> = outcomes.iter()
+            .filter(|o| match o.manipulation {
+                Manipulation::Truncation { .. } => kind == kinds[0],
+                Manipulation::Swap { .. } => kind == kinds[1],
+                Manipulation::Withholding { .. } => kind == kinds[2],
+                Manipulation::ProgramTruncation { .. } => kind == kinds[3]
+            })
+            .collect();
+        if tried.is_empty() {
+            continue;
+        }
+        let profitable: Vec<_> = tried.iter().filter(|o| o.is_profitable()).collect();
+        let best = profitable.iter().max_by(|a, b| a.gain.total_cmp(&b.gain));
+        println!("{}: {} tried, {} paid off ({:.1}%), {:.1} other applicants affected on average{}",
+                 kind, tried.len(), profitable.len(), profitable.len() as f32 / tried.len() as f32 * 100.0,
+                 tried.iter().map(|o| o.affected).sum::<usize>() as f32 / tried.len() as f32,
+                 match best {
+                     None => String::new(),
+                     Some(best) => format!(", best gain {:.2} places ({:?})", best.gain, best.manipulation)
+                 });
+    }
+}
+
+/// Matches with and without `scenario`, and reports how the outcome changes.
+pub fn report_scenario(parameters: &MatchParameters, scenario: &Scenario, seed: u64) {
+    let start = Instant::now();
+    let comparison = match scenario::run_scenario(parameters, scenario, seed) {
+        Err(ref e) => {
+            eprintln!("Error while running scenario: {:?}", e.to_string());
+            return
+        },
+        Ok(comparison) => comparison
+    };
+    println!("Ran baseline and {:?} in {:.2?}min.", scenario.transformations, start.elapsed().as_minutes());
+    let row = |name: &str, o: &OutcomeSummary| {
+        println!("{:>14}: {} applicants ({} couples), {} positions; {} matched ({:.1}%), {} couples matched, {} to their first choice, mean choice #{:.2}, {} positions unfilled",
+                 name, o.applicants, o.couples, o.positions, o.matched, o.matched as f32 / o.applicants.max(1) as f32 * 100.0,
+                 o.couples_matched, o.first_choice, o.mean_choice, o.unfilled_positions);
+    };
+    row("Baseline", &comparison.baseline);
+    row("Counterfactual", &comparison.counterfactual);
+    let (b, c) = (&comparison.baseline, &comparison.counterfactual);
+    println!("{:>14}: {:+} matched, {:+} couples matched, {:+} to their first choice, mean choice {:+.2}, {:+} positions unfilled; {} applicants matched differently",
+             "Difference", c.matched as i64 - b.matched as i64, c.couples_matched as i64 - b.couples_matched as i64,
+             c.first_choice as i64 - b.first_choice as i64, c.mean_choice - b.mean_choice,
+             c.unfilled_positions as i64 - b.unfilled_positions as i64, comparison.changed);
+    report_outcome_diff(&comparison.diff);
+}
+
+/// Runs the match as `run_simulation` does, and saves the outcome to `path` to compare
+/// with others later.
+pub fn save_outcome(mut parameters: MatchParameters, path: &str) {
+    prepare_match(&mut parameters);
+    let start = Instant::now();
+    let mut matcher = Matcher::new();
+    if let Err(ref e) = matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess) {
+        return eprintln!("Error while matching: {:?}", e.to_string());
+    }
+    let outcome = MatchOutcome::new(&parameters, &stability::assignment(&matcher));
+    match outcome.save(path) {
+        Err(ref e) => eprintln!("Error while writing {}: {:?}", path, e.to_string()),
+        Ok(_) => println!("Saved match outcome to {} in {:.2?}.", path, start.elapsed())
+    }
+}
+
+/// Compares two outcomes saved by `save_outcome`.
+pub fn report_diff(before: &str, after: &str) {
+    let open = |path: &str| MatchOutcome::open(path).map_err(|e| eprintln!("Error while reading {}: {:?}", path, e.to_string()));
+    if let (Ok(before), Ok(after)) = (open(before), open(after)) {
+        report_outcome_diff(&outcome::diff(&before, &after));
+    }
+}
+
+/// Reports who matched differently the second time, and which programs filled differently.
+pub fn report_outcome_diff(diff: &OutcomeDiff) {
+    if diff.is_empty() {
+        println!("The outcomes are the same.");
+        return;
+    }
+    println!("{} applicants matched differently: {} better and {} worse off by their own rank lists, {} as well off; {} newly matched and {} newly unmatched; {:+.2} places up their rank lists on average.",
+             diff.changes.len(), diff.count(Direction::Better), diff.count(Direction::Worse), diff.count(Direction::Same),
+             diff.newly_matched(), diff.newly_unmatched(), diff.mean_places());
+    for c in diff.changes.iter().take(10) {
+        let program = |p: Option<u32>| p.map_or("unmatched".to_string(), |p| p.to_string());
+        println!("  Applicant {}: {} -> {} ({:?}, {:+} places)", c.applicant, program(c.before), program(c.after), c.direction, c.places);
+    }
+    if diff.changes.len() > 10 {
+        println!("  ...");
+    }
+    println!("{} programs filled differently, {} positions filled in all before and {} after:",
+             diff.fills.len(), diff.matched_before, diff.matched_after);
+    for f in diff.fills.iter().take(10) {
+        println!("  Program {}: {}/{} -> {}/{}", f.program, f.filled_before, f.capacity_before, f.filled_after, f.capacity_after);
+    }
+    if diff.fills.len() > 10 {
+        println!("  ...");
+    }
+    if !diff.only_before.is_empty() || !diff.only_after.is_empty() {
+        println!("{} applicants are only in the first outcome and {} only in the second.", diff.only_before.len(), diff.only_after.len());
+    }
+}
+
+/// Runs the match with ties in programs' rank lists broken by each rule, and reports how
+/// the outcomes differ.
+pub fn report_tie_breaking(parameters: &MatchParameters, seed: u64) {
+    let start = Instant::now();
+    let outcomes = match ties::compare_tie_breaking(parameters, seed) {
+        Err(ref e) => {
+            eprintln!("Error while comparing tie-breaking rules: {:?}", e.to_string());
+            return
+        },
+        Ok(outcomes) => outcomes
+    };
+    println!("Compared tie-breaking rules in {:.2?}min.", start.elapsed().as_minutes());
+    for o in outcomes.iter() {
+        println!("{:?}: {} matched, {} to their first choice, mean choice #{:.2}, {} matched differently than with a single lottery",
+                 o.rule, o.matched, o.first_choice, o.mean_choice, o.changed);
+    }
+}
+
+/// Reports the programs left out of the match for certifying their rank lists late.
+pub fn report_excluded(excluded: &[timeline::Excluded]) {
+    if excluded.is_empty() {
+        return;
+    }
+    let applicants = excluded.iter().flat_map(|e| &e.ranked_by).collect::<std::collections::HashSet<_>>().len();
+    println!("Excluded {} programs that did not certify their rank lists by their deadline, ranked by {} applicants:",
+             excluded.len(), applicants);
+    for e in excluded.iter().take(10) {
+        let certified = match e.certified {
+            None => "never certified".to_string(),
+            Some(certified) => format!("certified {:.1} days late", (certified - e.deadline).num_minutes() as f32 / (24.0 * 60.0))
+        };
+        println!("  {} ({}): {}, ranked by {} applicants", e.name, e.program, certified, e.ranked_by.len());
+    }
+    if excluded.len() > 10 {
+        println!("  ...");
+    }
+}
+
+/// Reports how many applicants in each group of `by` matched, and matched their first
+/// choice. Applicants without a profile are left out.
+pub fn report_strata(matcher: &Matcher<Applicant, Program>, by: Stratification) {
+    // applicants, matched and matched to their first choice, by group
+    let mut strata: BTreeMap<String, (usize, usize, usize)> = BTreeMap::new();
+    let matched = matcher.matches.iter()
+        .flat_map(|m| m.1.iter().map(move |a| (*a, m.0.id())))
+        .filter(|(a, p)| matcher.linked.get(&a.id()) != Some(p))
+        .map(|(a, p)| (a, Some(p)));
+    let unmatched = matcher.unmatched_a.iter().map(|a| (*a, None));
+    for (a, p) in matched.chain(unmatched) {
+        let profile = match a.profile() {
+            None => continue,
+            Some(profile) => profile
+        };
+        let stratum = strata.entry(by.key(profile)).or_default();
+        stratum.0 += 1;
+        if let Some(p) = p {
+            stratum.1 += 1;
+            stratum.2 += (a.ranking.first() == Some(&p)) as usize;
+        }
+    }
+    if strata.is_empty() {
+        return;
+    }
+    println!("Matched applicants by {:?}:", by);
+    for (key, (applicants, matched, first_choice)) in strata {
+        println!("  {}: {} of {} ({:.1}%), {} ({:.1}%) matched their first choice",
+                 key, matched, applicants, matched as f32 / applicants as f32 * 100.0,
+                 first_choice, first_choice as f32 / applicants as f32 * 100.0);
+    }
+}
+
+/// Runs the supplemental offer round after a match and reports how many of the unfilled
+/// positions each round of offers fills.
+pub fn report_soap(matcher: &Matcher<Applicant, Program>) {
+    let unfilled = matcher.unfilled_positions();
+    if unfilled == 0 {
+        println!("No positions were left unfilled, so no supplemental offers were made.");
+        return;
+    }
+    let outcome = soap::run_soap(matcher);
+    for (i, round) in outcome.rounds.iter().enumerate() {
+        println!("Supplemental offer round {}: {} offers made, {} accepted (positions filled), {} declined, {} applicants waiting for a better offer",
+                 i + 1, round.offers, round.accepted, round.declined, round.waiting);
+    }
+    let filled = outcome.placed.len();
+    println!("Supplemental offers filled {} of {} unfilled positions ({:.1}%), {} applicants remain unmatched.",
+             filled, unfilled, filled as f32 / unfilled as f32 * 100.0, matcher.unmatched_a.len() - filled);
+}
+
+/// Matches applicants to the tracks of programs, moving the positions tracks leave unfilled
+/// to the tracks they revert to, and reports the positions moved in each round of reversion.
+/// Returns the tracks, as programs of their own with the positions they ended up with, for
+/// the match to be run on.
+pub fn revert_tracks(applicants: &Vec<Couple<Applicant>>, programs: &[Program]) -> Option<Vec<Program>> {
+    let mut tracks = tracks::track_programs(programs);
+    let rules = tracks::reversion_rules(programs);
+    let reversions = match tracks::revert_unfilled(applicants, &mut tracks, &rules) {
+        Err(ref e) => {
+            eprintln!("Error while matching tracks: {:?}", e.to_string());
+            return None
+        },
+        Ok(reversions) => reversions
+    };
+    for r in reversions.iter() {
+        println!("Reversion round {}: {} unfilled positions moved from track {} to track {}",
+                 r.round, r.positions, r.from, r.to);
+    }
+    let reverted: u32 = reversions.iter().map(|r| r.positions as u32).sum();
+    println!("Reverted {} positions across {} tracks.", reverted, reversions.len());
+    Some(tracks)
+}
+
+/// Finds (up to `limit`) stable matchings of a singles-only match and reports how far the
+/// applicant-optimal, program-optimal, median and egalitarian matchings are apart.
+pub fn report_stable_lattice(parameters: &MatchParameters, limit: usize) {
+    let start = Instant::now();
+
+    let lattice = match StableLattice::new(&parameters.applicants, &parameters.programs, limit) {
+        Err(ref e) => {
+            eprintln!("Error while finding stable matchings: {:?}", e.to_string());
+            return
+        },
+        Ok(lattice) => lattice
+    };
+    println!("Found {}{} stable matchings ({} rotations) in {:.2?}min.",
+             if lattice.is_complete() { "" } else { "at least " }, lattice.len(), lattice.num_rotations(),
+             start.elapsed().as_minutes());
+
+    let applicant_optimal = lattice.applicant_optimal();
+    let matched = applicant_optimal.len();
+    let mut selections = vec![
+        ("Applicant-optimal", applicant_optimal.clone()),
+        ("Program-optimal", lattice.program_optimal()),
+    ];
+    match lattice.median() {
+        Some(median) => selections.push(("Median", median)),
+        None => println!("Median matching: not every stable matching was found, so it is unknown")
+    }
+    selections.push(("Egalitarian", lattice.egalitarian()));
+    for (name, matching) in selections.iter() {
+        let moved = matching.iter().filter(|(a, p)| applicant_optimal.get(a) != Some(p)).count();
+        println!("{} matching: total rank {}, {} applicants ({:.1}%) matched differently than applicant-optimal",
+                 name, lattice.total_rank(matching), moved, moved as f32 / matched as f32 * 100.0);
+    }
 }
 
 pub fn animated_process<T, S, F>(v: &mut Vec<T>, s: S, mut f: F)
@@ -214,12 +638,55 @@ where
     for t in v {
         f(t);
         i += 1;
-        stdout.queue(cursor::SavePosition).unwrap();
-        stdout.write_all(s(i, len).as_ref()).unwrap();
-        stdout.queue(cursor::RestorePosition).unwrap();
-        stdout.flush().unwrap();
-        stdout.queue(cursor::RestorePosition).unwrap();
-        stdout.queue(terminal::Clear(terminal::ClearType::FromCursorDown)).unwrap();
+        print_progress(&mut stdout, s(i, len));
     }
     stdout.execute(cursor::Show).unwrap();
 }
+
+/// Like `animated_process`, but maps items in parallel, a chunk at a time so progress
+/// can still be shown. Results are in the same order as `v`.
+pub fn animated_par_map<T, R, S, F>(v: &[T], s: S, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    S: Fn(usize, usize) -> String,
+    F: Fn(&T) -> R + Sync
+{
+    let len = v.len();
+    let mut results = Vec::with_capacity(len);
+    let mut stdout = stdout();
+    for chunk in v.chunks(usize::max(len / 100, 1)) {
+        results.par_extend(chunk.par_iter().map(&f));
+        print_progress(&mut stdout, s(results.len(), len));
+    }
+    stdout.execute(cursor::Show).unwrap();
+    results
+}
+
+/// Like `animated_process`, but processes items in parallel, a chunk at a time so
+/// progress can still be shown.
+pub fn animated_par_process<T, S, F>(v: &mut [T], s: S, f: F)
+where
+    T: Send,
+    S: Fn(usize, usize) -> String,
+    F: Fn(&mut T) + Sync
+{
+    let len = v.len();
+    let mut i = 0usize;
+    let mut stdout = stdout();
+    for chunk in v.chunks_mut(usize::max(len / 100, 1)) {
+        i += chunk.len();
+        chunk.par_iter_mut().for_each(&f);
+        print_progress(&mut stdout, s(i, len));
+    }
+    stdout.execute(cursor::Show).unwrap();
+}
+
+fn print_progress(stdout: &mut Stdout, message: String) {
+    stdout.queue(cursor::SavePosition).unwrap();
+    stdout.write_all(message.as_ref()).unwrap();
+    stdout.queue(cursor::RestorePosition).unwrap();
+    stdout.flush().unwrap();
+    stdout.queue(cursor::RestorePosition).unwrap();
+    stdout.queue(terminal::Clear(terminal::ClearType::FromCursorDown)).unwrap();
+}
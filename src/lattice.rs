@@ -0,0 +1,492 @@
+use std::collections::{HashMap, HashSet};
+use crate::models::{Couple, HasCapacity, HasCouple};
+use crate::ranker::Rankable;
+use crate::stability::Assignment;
+
+#[derive(Debug)]
+pub enum LatticeError {
+    CouplesPresent(String),
+}
+
+impl std::fmt::Display for LatticeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LatticeError::CouplesPresent(s) => write!(f, "Couples present: {}", s),
+        }
+    }
+}
+
+/// Moving each applicant in a cycle to the next one's seat: `(applicant, from seat, to seat)`.
+struct Rotation {
+    moves: Vec<(usize, usize, usize)>,
+    /// Change in total rank (of both sides) from eliminating the rotation.
+    weight: i64,
+}
+
+/// A singles-only match where every program is split into seats of capacity 1, each with
+/// the program's rank list. Applicants rank a program's seats one after the other, so
+/// stable matchings of the seats are exactly the stable matchings of the programs.
+struct Seats {
+    applicant_ids: Vec<u32>,
+    seat_programs: Vec<u32>,
+    program_seats: HashMap<u32, Vec<usize>>,
+    /// Mutually acceptable applicants, in each program's order of preference.
+    program_lists: HashMap<u32, Vec<usize>>,
+    /// Mutually acceptable seats, in the applicant's order of preference.
+    lists: Vec<Vec<usize>>,
+    /// Position of each seat on an applicant's list.
+    positions: Vec<HashMap<usize, usize>>,
+    /// Position of each program on an applicant's rank list.
+    applicant_ranks: Vec<HashMap<u32, usize>>,
+    /// Position of each applicant on a program's rank list.
+    program_ranks: HashMap<u32, HashMap<usize, usize>>,
+}
+
+/// Every cycle reached by following `next` from each element, as its elements in order.
+fn cycles(next: &[Option<usize>]) -> Vec<Vec<usize>> {
+    let mut state = vec![0u8; next.len()];
+    let mut cycles = Vec::new();
+    for start in 0..next.len() {
+        let mut path = Vec::new();
+        let mut i = start;
+        while state[i] == 0 {
+            state[i] = 1;
+            path.push(i);
+            match next[i] {
+                None => break,
+                Some(j) => i = j
+            }
+        }
+        // a walk that runs into itself found a cycle
+        if state[i] == 1 && next[i].is_some() {
+            cycles.push(path[path.iter().position(|j| *j == i).unwrap()..].to_vec());
+        }
+        for j in path {
+            state[j] = 2;
+        }
+    }
+    cycles
+}
+
+impl Seats {
+    fn new<A, P>(applicants: &[Couple<A>], programs: &[P]) -> Seats
+    where A: Rankable<P>,
+          P: Rankable<A> + HasCapacity
+    {
+        let applicant_ids: Vec<u32> = applicants.iter().map(|c| c.0.id()).collect();
+        let index: HashMap<u32, usize> = applicant_ids.iter().enumerate().map(|(i, a)| (*a, i)).collect();
+        let program_ranks: HashMap<u32, HashMap<usize, usize>> = programs.iter().map(|p| (
+            p.id(),
+            p.ranking().into_iter().enumerate()
+                .filter_map(|(rank, a)| index.get(&a).map(|i| (*i, rank)))
+                .collect()
+        )).collect();
+
+        let mut seat_programs = Vec::new();
+        let mut program_seats: HashMap<u32, Vec<usize>> = HashMap::new();
+        for p in programs {
+            let seats = (0..p.capacity()).map(|_| {
+                seat_programs.push(p.id());
+                seat_programs.len() - 1
+            }).collect();
+            program_seats.insert(p.id(), seats);
+        }
+
+        let mut lists = Vec::new();
+        let mut applicant_ranks = Vec::new();
+        for (i, c) in applicants.iter().enumerate() {
+            let ranking = c.0.ranking();
+            let list: Vec<usize> = ranking.iter()
+                .filter(|p| program_ranks.get(p).is_some_and(|r| r.contains_key(&i)))
+                .flat_map(|p| program_seats[p].iter().copied())
+                .collect();
+            lists.push(list);
+            applicant_ranks.push(ranking.into_iter().enumerate().map(|(rank, p)| (p, rank)).rev().collect());
+        }
+        let positions: Vec<HashMap<usize, usize>> = lists.iter()
+            .map(|l| l.iter().enumerate().map(|(i, s)| (*s, i)).collect())
+            .collect();
+        let program_lists = programs.iter().map(|p| {
+            let mut list: Vec<usize> = program_ranks[&p.id()].keys()
+                .filter(|a| program_seats[&p.id()].first().is_some_and(|s| positions[**a].contains_key(s)))
+                .copied()
+                .collect();
+            list.sort_unstable_by_key(|a| program_ranks[&p.id()][a]);
+            (p.id(), list)
+        }).collect();
+
+        Seats { applicant_ids, seat_programs, program_seats, program_lists, lists, positions, applicant_ranks, program_ranks }
+    }
+
+    fn program_rank(&self, seat: usize, applicant: usize) -> usize {
+        self.program_ranks[&self.seat_programs[seat]][&applicant]
+    }
+
+    fn applicant_rank(&self, applicant: usize, seat: usize) -> usize {
+        self.applicant_ranks[applicant][&self.seat_programs[seat]]
+    }
+
+    /// Applicant-proposing deferred acceptance: the applicant-optimal stable matching,
+    /// as each applicant's seat.
+    fn applicant_optimal(&self) -> Vec<Option<usize>> {
+        let mut seat_of = vec![None; self.lists.len()];
+        let mut holder: Vec<Option<usize>> = vec![None; self.seat_programs.len()];
+        let mut next = vec![0usize; self.lists.len()];
+        let mut free: Vec<usize> = (0..self.lists.len()).rev().collect();
+        while let Some(a) = free.pop() {
+            while next[a] < self.lists[a].len() {
+                let s = self.lists[a][next[a]];
+                next[a] += 1;
+                match holder[s] {
+                    Some(b) if self.program_rank(s, b) < self.program_rank(s, a) => continue,
+                    Some(b) => {
+                        seat_of[b] = None;
+                        free.push(b);
+                    },
+                    None => {}
+                }
+                holder[s] = Some(a);
+                seat_of[a] = Some(s);
+                break;
+            }
+        }
+        seat_of
+    }
+
+    /// Where an applicant points in the matching: the first seat after their own that
+    /// would rather have them than whoever holds it, and that holder.
+    fn successor(&self, a: usize, seat_of: &[Option<usize>], holder: &[Option<usize>]) -> Option<(usize, usize)> {
+        let current = seat_of[a]?;
+        for s in self.lists[a][self.positions[a][&current] + 1..].iter() {
+            match holder[*s] {
+                // a seat no stable matching fills can't be part of a rotation
+                None => return None,
+                Some(b) if self.program_rank(*s, a) < self.program_rank(*s, b) => return Some((b, *s)),
+                Some(_) => {}
+            }
+        }
+        None
+    }
+
+    /// Every rotation exposed in the matching, as its moves in cycle order.
+    fn exposed_rotations(&self, seat_of: &[Option<usize>]) -> Vec<Vec<(usize, usize, usize)>> {
+        let holder = self.holders(seat_of);
+        let successors: Vec<Option<(usize, usize)>> = (0..seat_of.len())
+            .map(|a| self.successor(a, seat_of, &holder))
+            .collect();
+        cycles(&successors.iter().map(|s| s.map(|s| s.0)).collect::<Vec<_>>()).into_iter()
+            .map(|cycle| cycle.into_iter()
+                .map(|a| (a, seat_of[a].unwrap(), successors[a].unwrap().1))
+                .collect())
+            .collect()
+    }
+
+    /// Where a seat points in the matching: the first applicant after its holder on the
+    /// program's list who would rather have the seat than their own.
+    fn program_successor(&self, s: usize, seat_of: &[Option<usize>], holder: &[Option<usize>]) -> Option<usize> {
+        let current = holder[s]?;
+        let list = &self.program_lists[&self.seat_programs[s]];
+        for b in list[list.iter().position(|a| *a == current).unwrap() + 1..].iter() {
+            match seat_of[*b] {
+                // an applicant no stable matching places can't be part of a rotation
+                None => return None,
+                Some(t) if self.positions[*b][&s] < self.positions[*b][&t] => return Some(*b),
+                Some(_) => {}
+            }
+        }
+        None
+    }
+
+    /// Every rotation exposed in the matching that makes applicants better off, as the
+    /// moves undoing it.
+    fn exposed_program_rotations(&self, seat_of: &[Option<usize>]) -> Vec<Vec<(usize, usize, usize)>> {
+        let holder = self.holders(seat_of);
+        let successors: Vec<Option<usize>> = (0..holder.len())
+            .map(|s| self.program_successor(s, seat_of, &holder))
+            .collect();
+        let next_seats: Vec<Option<usize>> = successors.iter().map(|b| b.and_then(|b| seat_of[b])).collect();
+        cycles(&next_seats).into_iter()
+            .map(|cycle| cycle.into_iter()
+                .map(|s| {
+                    let b = successors[s].unwrap();
+                    (b, seat_of[b].unwrap(), s)
+                })
+                .collect())
+            .collect()
+    }
+
+    fn holders(&self, seat_of: &[Option<usize>]) -> Vec<Option<usize>> {
+        let mut holder = vec![None; self.seat_programs.len()];
+        for (a, s) in seat_of.iter().enumerate() {
+            if let Some(s) = s {
+                holder[*s] = Some(a);
+            }
+        }
+        holder
+    }
+
+    /// Each applicant's seat in a matching of the programs, filling each program's seats
+    /// in its order of preference.
+    fn seats_from(&self, assignment: &Assignment) -> Vec<Option<usize>> {
+        let mut seat_of = vec![None; self.applicant_ids.len()];
+        for (p, list) in self.program_lists.iter() {
+            let matched = list.iter().filter(|a| assignment.get(&self.applicant_ids[**a]) == Some(p));
+            for (a, s) in matched.zip(self.program_seats[p].iter()) {
+                seat_of[*a] = Some(*s);
+            }
+        }
+        seat_of
+    }
+
+    fn rotation(&self, moves: Vec<(usize, usize, usize)>) -> Rotation {
+        let mut weight = 0i64;
+        for (i, (a, from, to)) in moves.iter().enumerate() {
+            // the seat `a` moves to was held by the next applicant in the cycle
+            let (displaced, _, _) = moves[(i + 1) % moves.len()];
+            weight += self.applicant_rank(*a, *to) as i64 - self.applicant_rank(*a, *from) as i64;
+            weight += self.program_rank(*to, *a) as i64 - self.program_rank(*to, displaced) as i64;
+        }
+        Rotation { moves, weight }
+    }
+}
+
+/// The applicant-optimal stable matching of a singles-only match, found from any other
+/// stable matching by undoing rotations until no applicant can do better.
+///
+/// `stable` must be stable; this is cheaper than matching from scratch when it is close to
+/// the applicant-optimal matching already.
+pub fn applicant_optimal_from<A, P>(applicants: &[Couple<A>], programs: &[P], stable: &Assignment) -> Result<Assignment, LatticeError>
+where A: Rankable<P> + HasCouple,
+      P: Rankable<A> + HasCapacity
+{
+    if let Some(c) = applicants.iter().find(|c| c.1.is_some() || c.0.get_couple().is_some()) {
+        return Err(LatticeError::CouplesPresent(format!("applicant {} is in a couple", c.0.id())));
+    }
+    let seats = Seats::new(applicants, programs);
+    let mut seat_of = seats.seats_from(stable);
+    loop {
+        let exposed = seats.exposed_program_rotations(&seat_of);
+        if exposed.is_empty() {
+            break;
+        }
+        for (a, _, to) in exposed.into_iter().flatten() {
+            seat_of[a] = Some(to);
+        }
+    }
+    Ok(seat_of.iter().enumerate()
+        .filter_map(|(a, s)| s.map(|s| (seats.applicant_ids[a], seats.seat_programs[s])))
+        .collect())
+}
+
+/// The stable matchings of a singles-only match, found by eliminating rotations from the
+/// applicant-optimal matching down to the program-optimal one.
+///
+/// Each matching is kept as the set of rotations eliminated to reach it, so only the
+/// matchings asked for are built in full.
+pub struct StableLattice {
+    seats: Seats,
+    applicant_optimal: Vec<Option<usize>>,
+    rotations: Vec<Rotation>,
+    /// Rotations each applicant takes part in, in the order they are eliminated.
+    applicant_rotations: Vec<Vec<usize>>,
+    matchings: Vec<Vec<u64>>,
+    complete: bool,
+}
+
+impl StableLattice {
+    /// Finds up to `limit` stable matchings. Every rotation is always found, so the
+    /// applicant-optimal and program-optimal matchings are exact even past the limit.
+    pub fn new<A, P>(applicants: &[Couple<A>], programs: &[P], limit: usize) -> Result<StableLattice, LatticeError>
+    where A: Rankable<P> + HasCouple,
+          P: Rankable<A> + HasCapacity
+    {
+        if let Some(c) = applicants.iter().find(|c| c.1.is_some() || c.0.get_couple().is_some()) {
+            return Err(LatticeError::CouplesPresent(format!("applicant {} is in a couple", c.0.id())));
+        }
+        let seats = Seats::new(applicants, programs);
+        let applicant_optimal = seats.applicant_optimal();
+
+        // eliminating exposed rotations until there are none reaches the program-optimal
+        // matching, and eliminates every rotation exactly once on the way
+        let mut rotations = Vec::new();
+        let mut seat_of = applicant_optimal.clone();
+        loop {
+            let exposed = seats.exposed_rotations(&seat_of);
+            if exposed.is_empty() {
+                break;
+            }
+            for moves in exposed {
+                for (a, _, to) in moves.iter() {
+                    seat_of[*a] = Some(*to);
+                }
+                rotations.push(seats.rotation(moves));
+            }
+        }
+        let mut applicant_rotations = vec![Vec::new(); applicant_optimal.len()];
+        for (r, rotation) in rotations.iter().enumerate() {
+            for (a, _, _) in rotation.moves.iter() {
+                applicant_rotations[*a].push(r);
+            }
+        }
+
+        let mut lattice = StableLattice {
+            seats,
+            applicant_optimal,
+            rotations,
+            applicant_rotations,
+            matchings: Vec::new(),
+            complete: true,
+        };
+        lattice.enumerate(limit);
+        Ok(lattice)
+    }
+
+    /// Depth-first search of the matchings reachable by eliminating exposed rotations.
+    fn enumerate(&mut self, limit: usize) {
+        let keys: HashMap<Vec<(usize, usize)>, usize> = self.rotations.iter().enumerate()
+            .map(|(r, rotation)| (Self::key(&rotation.moves), r))
+            .collect();
+        let mut seen: HashSet<Vec<u64>> = HashSet::new();
+        let mut stack = vec![(vec![0u64; self.words()], self.applicant_optimal.clone())];
+        seen.insert(stack[0].0.clone());
+        while let Some((eliminated, seat_of)) = stack.pop() {
+            if self.matchings.len() >= usize::max(limit, 1) {
+                self.complete = false;
+                break;
+            }
+            for moves in self.seats.exposed_rotations(&seat_of) {
+                let r = keys[&Self::key(&moves)];
+                let mut next = eliminated.clone();
+                next[r / 64] |= 1 << (r % 64);
+                if seen.insert(next.clone()) {
+                    let mut next_seats = seat_of.clone();
+                    for (a, _, to) in moves {
+                        next_seats[a] = Some(to);
+                    }
+                    stack.push((next, next_seats));
+                }
+            }
+            self.matchings.push(eliminated);
+        }
+    }
+
+    /// Length of the bitsets of eliminated rotations.
+    fn words(&self) -> usize {
+        self.rotations.len().div_ceil(64)
+    }
+
+    fn key(moves: &[(usize, usize, usize)]) -> Vec<(usize, usize)> {
+        let mut key: Vec<(usize, usize)> = moves.iter().map(|(a, from, _)| (*a, *from)).collect();
+        key.sort_unstable();
+        key
+    }
+
+    fn is_eliminated(eliminated: &[u64], r: usize) -> bool {
+        eliminated[r / 64] & (1 << (r % 64)) != 0
+    }
+
+    /// Each applicant's seat once the given rotations are eliminated.
+    fn seats_of(&self, eliminated: &[u64]) -> Vec<Option<usize>> {
+        let mut seat_of = self.applicant_optimal.clone();
+        for (a, rotations) in self.applicant_rotations.iter().enumerate() {
+            // an applicant's rotations are eliminated in order, so the last one decides
+            if let Some(r) = rotations.iter().rev().find(|r| Self::is_eliminated(eliminated, **r)) {
+                seat_of[a] = self.rotations[*r].moves.iter().find(|m| m.0 == a).map(|m| m.2);
+            }
+        }
+        seat_of
+    }
+
+    fn assignment(&self, seat_of: &[Option<usize>]) -> Assignment {
+        seat_of.iter().enumerate()
+            .filter_map(|(a, s)| s.map(|s| (self.seats.applicant_ids[a], self.seats.seat_programs[s])))
+            .collect()
+    }
+
+    /// Number of stable matchings found.
+    pub fn len(&self) -> usize {
+        self.matchings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matchings.is_empty()
+    }
+
+    /// Whether every stable matching was found, rather than stopping at the limit.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn num_rotations(&self) -> usize {
+        self.rotations.len()
+    }
+
+    pub fn matching(&self, i: usize) -> Assignment {
+        self.assignment(&self.seats_of(&self.matchings[i]))
+    }
+
+    pub fn matchings(&self) -> impl Iterator<Item = Assignment> + '_ {
+        (0..self.len()).map(move |i| self.matching(i))
+    }
+
+    pub fn applicant_optimal(&self) -> Assignment {
+        self.assignment(&self.applicant_optimal)
+    }
+
+    pub fn program_optimal(&self) -> Assignment {
+        self.assignment(&self.seats_of(&vec![u64::MAX; self.words()]))
+    }
+
+    /// Gives every applicant the median of the programs they are matched to across the
+    /// stable matchings, which is itself a stable matching. `None` if the lattice stopped at
+    /// its limit, as the median of only some of them need not be stable.
+    pub fn median(&self) -> Option<Assignment> {
+        if !self.complete {
+            return None;
+        }
+        // how many of their rotations each applicant has been through, per matching
+        let mut counts: Vec<Vec<usize>> = self.applicant_rotations.iter().map(|r| vec![0; r.len() + 1]).collect();
+        for eliminated in self.matchings.iter() {
+            for (a, rotations) in self.applicant_rotations.iter().enumerate() {
+                let done = rotations.iter().filter(|r| Self::is_eliminated(eliminated, **r)).count();
+                counts[a][done] += 1;
+            }
+        }
+        let median = self.len().div_ceil(2);
+        let seat_of: Vec<Option<usize>> = counts.iter().enumerate().map(|(a, counts)| {
+            let mut seen = 0;
+            let done = counts.iter().position(|c| {
+                seen += c;
+                seen >= median
+            }).unwrap_or(0);
+            match done {
+                0 => self.applicant_optimal[a],
+                d => self.rotations[self.applicant_rotations[a][d - 1]].moves.iter().find(|m| m.0 == a).map(|m| m.2)
+            }
+        }).collect();
+        Some(self.assignment(&seat_of))
+    }
+
+    /// The stable matching found with the lowest total rank, counting each applicant's
+    /// rank of their program and each program's rank of its applicants.
+    pub fn egalitarian(&self) -> Assignment {
+        let egalitarian = self.matchings.iter()
+            .min_by_key(|eliminated| (0..self.rotations.len())
+                .filter(|r| Self::is_eliminated(eliminated, *r))
+                .map(|r| self.rotations[r].weight)
+                .sum::<i64>())
+            .unwrap();
+        self.assignment(&self.seats_of(egalitarian))
+    }
+
+    /// Total rank of a stable matching, as minimised by `egalitarian`.
+    pub fn total_rank(&self, assignment: &Assignment) -> i64 {
+        let index: HashMap<u32, usize> = self.seats.applicant_ids.iter().enumerate().map(|(i, a)| (*a, i)).collect();
+        assignment.iter()
+            .map(|(a, p)| {
+                let a = index[a];
+                (self.seats.applicant_ranks[a][p] + self.seats.program_ranks[p][&a]) as i64
+            })
+            .sum()
+    }
+}
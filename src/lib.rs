@@ -0,0 +1,18 @@
+pub mod models;
+pub mod matcher;
+pub mod parameters;
+pub mod ranker;
+pub mod driver;
+pub mod stability;
+pub mod reference;
+pub mod lattice;
+pub mod soap;
+pub mod tracks;
+pub mod timeline;
+pub mod lifecycle;
+pub mod ties;
+pub mod audit;
+pub mod replay;
+pub mod manipulation;
+pub mod scenario;
+pub mod outcome;
@@ -0,0 +1,76 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::models::{Applicant, Couple, MatchStatus, Program};
+use crate::parameters::{Delta, MatchParameters};
+use crate::stability::Assignment;
+
+fn all_mut(applicants: &mut [Couple<Applicant>]) -> impl Iterator<Item = &mut Applicant> {
+    applicants.iter_mut().flat_map(|c| std::iter::once(&mut c.0).chain(c.1.as_mut()))
+}
+
+/// Moves applicants on to the furthest stage the applications programs received from them
+/// and their rank lists got them to. Applicants are never moved back a stage.
+pub fn record_applications(applicants: &mut [Couple<Applicant>], programs: &[Program]) {
+    // whether each applicant was interviewed anywhere, by applicant id
+    let mut interviewed: HashMap<u32, bool> = HashMap::new();
+    for a in programs.iter().flat_map(|p| &p.applications) {
+        *interviewed.entry(a.applicant).or_insert(false) |= a.interviewed;
+    }
+    for a in all_mut(applicants) {
+        let stage = match (a.ranking.is_empty(), interviewed.get(&a.id)) {
+            (false, _) => MatchStatus::Ranked,
+            (true, Some(true)) => MatchStatus::Interviewed,
+            (true, Some(false)) => MatchStatus::Applied,
+            (true, None) => MatchStatus::Registered
+        };
+        a.status = a.status.max(stage);
+    }
+}
+
+/// Certifies the rank lists of ranked applicants who still have anyone on them, which
+/// puts them in the match.
+pub fn certify(applicants: &mut [Couple<Applicant>]) {
+    for a in all_mut(applicants) {
+        if a.status == MatchStatus::Ranked && !a.ranking.is_empty() {
+            a.status = MatchStatus::Certified;
+        }
+    }
+}
+
+/// Withdraws an applicant from the match as `Delta::Withdrawal` does, but keeps them in
+/// `parameters`, on their own and with no rank list, so their status can still be looked up.
+pub fn withdraw(parameters: &mut MatchParameters, applicant: u32) {
+    let withdrawn = parameters.applicants.iter()
+        .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+        .find(|a| a.id == applicant)
+        .cloned();
+    if let Some(mut a) = withdrawn {
+        parameters.apply(&Delta::Withdrawal { applicant });
+        a.status = MatchStatus::Withdrawn;
+        a.couple = None;
+        a.ranking.clear();
+        a.supplemental.clear();
+        parameters.applicants.push(Couple(a, None));
+        parameters.num_applicants += 1;
+    }
+}
+
+/// Records whether each applicant who went into the match matched.
+pub fn record_outcome(applicants: &mut [Couple<Applicant>], assigned: &Assignment) {
+    for a in all_mut(applicants) {
+        if a.status == MatchStatus::Certified {
+            a.status = match assigned.contains_key(&a.id) {
+                true => MatchStatus::Matched,
+                false => MatchStatus::Unmatched
+            };
+        }
+    }
+}
+
+/// Applicants by status, each in the order they are in `applicants`.
+pub fn by_status(applicants: &[Couple<Applicant>]) -> BTreeMap<MatchStatus, Vec<u32>> {
+    let mut statuses: BTreeMap<MatchStatus, Vec<u32>> = BTreeMap::new();
+    for a in applicants.iter().flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref())) {
+        statuses.entry(a.status).or_default().push(a.id);
+    }
+    statuses
+}
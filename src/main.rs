@@ -1,17 +1,48 @@
 use std::time::Instant;
-use crate::driver::{generate_match_parameters, run_simulation};
-use crate::parameters::MatchParameters;
-
-mod models;
-mod matcher;
-mod parameters;
-mod ranker;
-mod driver;
+use residency_match::driver::{generate_match_parameters, report_explanation, report_manipulation, report_diff, report_replay, report_scenario, run_simulation, save_match_events, save_outcome};
+use residency_match::manipulation::ManipulationPolicy;
+use residency_match::scenario::Scenario;
+use residency_match::parameters::MatchParameters;
 
 const NUM_APPLICANTS: usize = 50000;
 const NUM_PROGRAMS: usize = 10000;
 
 fn main() {
+    // `explain <datafile> <applicant>` explains one applicant's result, `events <datafile>
+    // <eventfile>` saves the match's events, `replay <eventfile> <position>` replays them and
+    // `manipulate <datafile>` tries out manipulations; `whatif <datafile> <transformation>...`
+    // compares the match with a scenario's (e.g. `whatif data.bin couples=2 capacity=+1`);
+    // `outcome <datafile> <outcomefile>` saves the match's outcome and `diff <outcomefile>
+    // <outcomefile>` compares two
+    let args: Vec<String> = std::env::args().collect();
+    let number = |arg: &str| arg.parse::<usize>().map_err(|_| eprintln!("Not a number: {}", arg));
+    match args.iter().map(|a| a.as_str()).collect::<Vec<_>>().as_slice() {
+        [_, "explain", datafile, applicant] => {
+            if let Ok(applicant) = number(applicant) {
+                report_explanation(load_params(&Some(datafile)), applicant as u32);
+            }
+            return
+        },
+        [_, "events", datafile, eventfile] => return save_match_events(load_params(&Some(datafile)), eventfile),
+        [_, "outcome", datafile, outcomefile] => return save_outcome(load_params(&Some(datafile)), outcomefile),
+        [_, "diff", before, after] => return report_diff(before, after),
+        [_, "manipulate", datafile] => return report_manipulation(&load_params(&Some(datafile)), &ManipulationPolicy::default(), 0),
+        [_, "replay", eventfile, position] => {
+            if let Ok(position) = number(position) {
+                report_replay(eventfile, position);
+            }
+            return
+        },
+        [_, "whatif", datafile, transformations @ ..] if !transformations.is_empty() => {
+            match Scenario::parse(transformations) {
+                Err(ref e) => eprintln!("{}", e),
+                Ok(scenario) => report_scenario(&load_params(&Some(datafile)), &scenario, 0)
+            }
+            return
+        },
+        _ => {}
+    }
+
     let datafile: Option<&str> = None;
 
     let parameters = load_params(&datafile);
@@ -0,0 +1,224 @@
+use rand::Rng;
+use crate::matcher::{MatchError, Matcher};
+use crate::models::{generator, Applicant, Couple, Program};
+use crate::parameters::{Delta, MatchParameters};
+use crate::stability::{self, Assignment};
+
+/// A way an applicant (or couple) or a program might misreport, to try for a better match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Manipulation {
+    /// The applicant ranks only the first `keep` entries of their rank list. A couple is
+    /// identified by its first partner, and cuts its joint rank list.
+    Truncation { applicant: u32, keep: usize },
+    /// The applicant (or couple) swaps the entries at `at` and `at + 1` on their rank list.
+    Swap { applicant: u32, at: usize },
+    /// The program offers `withheld` fewer positions than it has.
+    Withholding { program: u32, withheld: u16 },
+    /// The program ranks only the first `keep` applicants on its rank list.
+    ProgramTruncation { program: u32, keep: usize },
+}
+
+/// How a manipulation worked out, judged by the manipulator's true rank list.
+#[derive(Debug, Clone)]
+pub struct ManipulationOutcome {
+    pub manipulation: Manipulation,
+    /// For an applicant or couple, how many places up their rank list they matched. For a
+    /// program, how many places up its rank list its applicants were on average, over all
+    /// its positions, with an empty position counting as one past the end of the list.
+    /// Negative for a loss.
+    pub gain: f32,
+    /// Applicants other than the manipulator who matched differently.
+    pub affected: usize,
+}
+
+impl ManipulationOutcome {
+    pub fn is_profitable(&self) -> bool {
+        self.gain > 0.0
+    }
+}
+
+/// How many applicants and programs to try manipulations for.
+#[derive(Debug, Clone)]
+pub struct ManipulationPolicy {
+    /// Applicants (single or couples) sampled from those who didn't match their first choice.
+    pub applicants: usize,
+    /// Programs sampled from those with positions and a rank list.
+    pub programs: usize,
+    /// Most manipulations tried for each.
+    pub attempts: usize,
+}
+
+impl Default for ManipulationPolicy {
+    /// Ten applicants and ten programs, four manipulations each.
+    fn default() -> Self {
+        ManipulationPolicy {
+            applicants: 10,
+            programs: 10,
+            attempts: 4,
+        }
+    }
+}
+
+fn unit(parameters: &MatchParameters, applicant: u32) -> Option<&Couple<Applicant>> {
+    parameters.applicants.iter().find(|c| c.0.id == applicant)
+}
+
+/// Where a single applicant or couple matched on their rank list, or its length if they didn't.
+fn position(c: &Couple<Applicant>, assigned: &Assignment) -> usize {
+    match &c.1 {
+        None => assigned.get(&c.0.id).and_then(|p| c.0.ranking.iter().position(|q| q == p)),
+        Some(b) => match (assigned.get(&c.0.id), assigned.get(&b.id)) {
+            (Some(p), Some(q)) => c.0.ranking.iter().zip(b.ranking.iter()).position(|pair| pair == (p, q)),
+            _ => None
+        }
+    }.unwrap_or(c.0.ranking.len())
+}
+
+/// Average place on the program's rank list of the applicants it matched, over all its positions.
+fn score(program: &Program, assigned: &Assignment) -> f32 {
+    if program.capacity == 0 {
+        return 0.0;
+    }
+    let mut ranks: Vec<usize> = assigned.iter()
+        .filter(|(_, p)| **p == program.id)
+        .filter_map(|(a, _)| program.ranking.iter().position(|r| r == a))
+        .collect();
+    ranks.resize(usize::max(ranks.len(), program.capacity as usize), program.ranking.len());
+    ranks.iter().sum::<usize>() as f32 / ranks.len() as f32
+}
+
+/// The parameters as the manipulator misreports them, and the change as a `Delta` for
+/// `Matcher::rematch` (for a couple, the change to the first partner, which puts the couple
+/// back into the match).
+pub fn manipulated(parameters: &MatchParameters, manipulation: &Manipulation) -> (MatchParameters, Delta) {
+    let mut parameters = parameters.clone();
+    let mut deltas: Vec<Delta> = match *manipulation {
+        Manipulation::Truncation { applicant, .. } | Manipulation::Swap { applicant, .. } => match unit(&parameters, applicant) {
+            None => vec![Delta::ApplicantRanking { applicant, ranking: Vec::new() }],
+            Some(c) => std::iter::once(&c.0).chain(c.1.as_ref()).map(|a| {
+                let mut ranking = a.ranking.clone();
+                match *manipulation {
+                    Manipulation::Swap { at, .. } if at + 1 < ranking.len() => ranking.swap(at, at + 1),
+                    Manipulation::Truncation { keep, .. } => ranking.truncate(keep),
+                    _ => {}
+                }
+                Delta::ApplicantRanking { applicant: a.id, ranking }
+            }).collect()
+        },
+        Manipulation::Withholding { program, withheld } => {
+            let capacity = parameters.programs.iter().find(|p| p.id == program).map_or(0, |p| p.capacity);
+            vec![Delta::Capacity { program, capacity: capacity.saturating_sub(withheld) }]
+        },
+        Manipulation::ProgramTruncation { program, keep } => {
+            let mut ranking = parameters.programs.iter().find(|p| p.id == program).map_or(Vec::new(), |p| p.ranking.clone());
+            ranking.truncate(keep);
+            vec![Delta::ProgramRanking { program, ranking }]
+        }
+    };
+    for delta in deltas.iter() {
+        parameters.apply(delta);
+    }
+    (parameters, deltas.swap_remove(0))
+}
+
+/// Matches with the manipulation, starting from the honest result `previous`. Without couples
+/// catching up is the same as matching from scratch; with them, the match is run again.
+fn rerun(parameters: &MatchParameters, previous: &Assignment, manipulation: &Manipulation) -> Result<Assignment, MatchError> {
+    let (parameters, delta) = manipulated(parameters, manipulation);
+    let mut matcher = Matcher::new();
+    match parameters.applicants.iter().any(|c| c.1.is_some()) {
+        true => matcher.run_match(&parameters.applicants, &parameters.programs)?,
+        false => matcher.rematch(&parameters.applicants, &parameters.programs, previous, &delta)?
+    }
+    Ok(stability::assignment(&matcher))
+}
+
+/// Tries a manipulation against the honest result `previous`, and judges it by the
+/// manipulator's true rank list in `parameters`.
+pub fn try_manipulation(parameters: &MatchParameters, previous: &Assignment, manipulation: Manipulation) -> Result<ManipulationOutcome, MatchError> {
+    let assigned = rerun(parameters, previous, &manipulation)?;
+    let (gain, manipulators) = match manipulation {
+        Manipulation::Truncation { applicant, .. } | Manipulation::Swap { applicant, .. } => match unit(parameters, applicant) {
+            None => (0.0, Vec::new()),
+            Some(c) => (
+                position(c, previous) as f32 - position(c, &assigned) as f32,
+                std::iter::once(c.0.id).chain(c.1.as_ref().map(|b| b.id)).collect()
+            )
+        },
+        Manipulation::Withholding { program, .. } | Manipulation::ProgramTruncation { program, .. } => {
+            match parameters.programs.iter().find(|p| p.id == program) {
+                None => (0.0, Vec::new()),
+                Some(p) => (score(p, previous) - score(p, &assigned), Vec::new())
+            }
+        }
+    };
+    let affected = parameters.applicants.iter()
+        .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+        .filter(|a| !manipulators.contains(&a.id) && previous.get(&a.id) != assigned.get(&a.id))
+        .count();
+    Ok(ManipulationOutcome { manipulation, gain, affected })
+}
+
+/// Picks up to `n` of `candidates` at random.
+fn sample<T>(candidates: Vec<T>, n: usize, seed: u64) -> Vec<T> {
+    let mut rng = generator::seeded_rng(seed);
+    let mut keyed: Vec<(u64, T)> = candidates.into_iter().map(|c| (rng.random(), c)).collect();
+    keyed.sort_by_key(|(k, _)| *k);
+    keyed.into_iter().take(n).map(|(_, c)| c).collect()
+}
+
+/// Manipulations worth trying for an applicant or couple who matched at `position` on their
+/// rank list: cutting the list short above where they matched, and moving something above
+/// it up a place, closest to where they matched first.
+fn applicant_manipulations(c: &Couple<Applicant>, position: usize, attempts: usize) -> Vec<Manipulation> {
+    let applicant = c.0.id;
+    let truncations = (1..=usize::min(position, c.0.ranking.len().saturating_sub(1))).rev()
+        .map(move |keep| Manipulation::Truncation { applicant, keep });
+    let swaps = (0..usize::min(position, c.0.ranking.len().saturating_sub(1))).rev()
+        .map(move |at| Manipulation::Swap { applicant, at });
+    let mut manipulations: Vec<Manipulation> = Vec::new();
+    let (mut truncations, mut swaps) = (truncations.peekable(), swaps.peekable());
+    while manipulations.len() < attempts && (truncations.peek().is_some() || swaps.peek().is_some()) {
+        manipulations.extend(truncations.next());
+        manipulations.extend(swaps.next());
+    }
+    manipulations.truncate(attempts);
+    manipulations
+}
+
+/// Manipulations worth trying for a program: dropping the weakest applicant it matched and
+/// everyone below from its rank list, and withholding positions, keeping at least one.
+fn program_manipulations(program: &Program, assigned: &Assignment, attempts: usize) -> Vec<Manipulation> {
+    let weakest = assigned.iter()
+        .filter(|(_, p)| **p == program.id)
+        .filter_map(|(a, _)| program.ranking.iter().position(|r| r == a))
+        .max();
+    weakest.map(|keep| Manipulation::ProgramTruncation { program: program.id, keep }).into_iter()
+        .chain((1..program.capacity).map(|withheld| Manipulation::Withholding { program: program.id, withheld }))
+        .take(attempts)
+        .collect()
+}
+
+/// Runs the match honestly, then tries manipulations for a sample of applicants and programs,
+/// matching again for each to see whether it would have paid off. Applicant-proposing
+/// deferred acceptance can't be gamed by single applicants, but couples and programs can
+/// sometimes gain.
+pub fn analyze_manipulations(parameters: &MatchParameters, policy: &ManipulationPolicy, seed: u64) -> Result<Vec<ManipulationOutcome>, MatchError> {
+    let mut matcher = Matcher::new();
+    matcher.run_match(&parameters.applicants, &parameters.programs)?;
+    let previous = stability::assignment(&matcher);
+
+    let applicants: Vec<(&Couple<Applicant>, usize)> = parameters.applicants.iter()
+        .map(|c| (c, position(c, &previous)))
+        .filter(|(_, position)| *position > 0)
+        .collect();
+    let programs: Vec<&Program> = parameters.programs.iter()
+        .filter(|p| p.capacity > 0 && !p.ranking.is_empty())
+        .collect();
+    let manipulations = sample(applicants, policy.applicants, seed).into_iter()
+        .flat_map(|(c, position)| applicant_manipulations(c, position, policy.attempts))
+        .chain(sample(programs, policy.programs, seed ^ 1).into_iter()
+            .flat_map(|p| program_manipulations(p, &previous, policy.attempts)));
+
+    manipulations.map(|m| try_manipulation(parameters, &previous, m)).collect()
+}
@@ -1,8 +1,15 @@
 use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io::{stdout, Write};
 use crossterm::{cursor, terminal, ExecutableCommand, QueueableCommand};
-use crate::models::{HasCouple, HasCapacity, Couple};
+use crate::audit::{EventLog, EventSink, MatchEvent, Proposal, Rejection};
+use crate::models::{HasCouple, HasCapacity, HasSupplemental, Couple};
+use crate::lattice;
+use crate::parameters::Delta;
 use crate::ranker::Rankable;
+use crate::stability::{self, Assignment, BlockingPair};
 
 #[derive(Debug)]
 pub enum MatchError {
@@ -19,19 +26,107 @@ impl MatchError {
     }
 }
 
+/// Number of times a couple may attempt to match before we assume it is stuck in a cycle
+/// of displacing (and being displaced by) other couples, and leave it unmatched.
+const MAX_COUPLE_ATTEMPTS: usize = 100;
+
+/// Number of times blocking applicants may be put back into the match before we give up
+/// on reaching a stable result.
+const MAX_REPROCESSING_ROUNDS: usize = 100;
+
+/// What to do when a match ends with blocking pairs. Only couples can cause this: with
+/// couples a stable match need not exist. Linked preliminary positions are reprocessed
+/// either way (see `run_match_`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InstabilityResolution {
+    /// Keep the result; its blocking pairs are still reported.
+    #[default]
+    Report,
+    /// Like the NRMP's algorithm, put applicants in blocking pairs back into the match to
+    /// try for the programs they prefer, until no blocking pairs are left or the same result
+    /// keeps coming up (the match is cycling), in which case the last result is kept.
+    Reprocess,
+}
+
+/// How far a finished match is from stable.
+#[derive(Debug, Clone, Default)]
+pub struct InstabilityReport {
+    /// Couples that kept displacing (and being displaced by) other couples until they were
+    /// left unmatched.
+    pub cycling_couples: Vec<(u32, u32)>,
+    /// Blocking pairs left in the result.
+    pub blocking_pairs: Vec<BlockingPair>,
+    /// Rounds of putting blocking applicants back into the match.
+    pub rounds: usize,
+    /// Whether reprocessing stopped because results came up again whichever order blocking
+    /// applicants were put back in.
+    pub repeated: bool,
+}
+
+impl InstabilityReport {
+    /// Whether no blocking pairs are left. Couples given up on are in `cycling_couples`
+    /// either way.
+    pub fn is_stable(&self) -> bool {
+        self.blocking_pairs.is_empty()
+    }
+
+    /// Every couple that was cycling or is in a blocking pair, each once.
+    pub fn unstable_couples(&self) -> Vec<(u32, u32)> {
+        let blocking = self.blocking_pairs.iter().filter_map(|b| match b {
+            BlockingPair::Couple { applicants, .. } => Some(*applicants),
+            BlockingPair::Single { .. } | BlockingPair::Supplemental { .. } => None
+        });
+        let mut seen = HashSet::new();
+        self.cycling_couples.iter().copied().chain(blocking)
+            .map(|(a, b)| (u32::min(a, b), u32::max(a, b)))
+            .filter(|c| seen.insert(*c))
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct Matcher<'a, A, P>
-where A: Rankable<P> + HasCouple + Clone,
+where A: Rankable<P> + HasCouple + HasSupplemental + Clone,
       P: Rankable<A> + HasCapacity
 {
     pub matches: Vec<(&'a P, Vec<&'a A>)>,
     pub unmatched_a: Vec<&'a A>,
     pub unmatched_p: Vec<&'a P>,
+    pub instability: InstabilityReport,
+    /// Preliminary positions held alongside an advanced program, by applicant id. These
+    /// applicants are also in `matches` under their preliminary program.
+    pub linked: Assignment,
+    /// Everything that happens in the match, in order, if `record_events` or `stream_events`
+    /// was called.
+    pub events: EventLog,
+    couple_attempts: HashMap<u32, usize>,
+}
+
+/// Why a program turns down one partner of a couple proposing a pair of programs: `rank`
+/// is where the program ranked them, `capacity` its positions, and `weakest_rank` where it
+/// ranked the applicant they would have to displace, if anyone.
+fn couple_rejection(rank: Option<usize>, capacity: u16, weakest_rank: Option<usize>) -> Rejection {
+    match (rank, weakest_rank) {
+        _ if capacity == 0 => Rejection::NoPositions,
+        (None, _) => Rejection::NotRanked,
+        (Some(rank), Some(weakest_rank)) if rank > weakest_rank => Rejection::Full { rank, weakest_rank },
+        _ => Rejection::PartnerRejected
+    }
+}
+
+impl<'a, A, P> Default for Matcher<'a, A, P>
+where
+    A: Rankable<P> + HasCouple + HasSupplemental + Clone,
+    P: Rankable<A> + HasCapacity
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a, A, P> Matcher<'a, A, P>
 where
-    A: Rankable<P> + HasCouple + Clone,
+    A: Rankable<P> + HasCouple + HasSupplemental + Clone,
     P: Rankable<A> + HasCapacity
 {
     pub fn new() -> Matcher<'a, A, P> {
@@ -39,20 +134,61 @@ where
             matches: Vec::new(),
             unmatched_a: Vec::new(),
             unmatched_p: Vec::new(),
+            instability: InstabilityReport::default(),
+            linked: Assignment::new(),
+            events: EventLog::default(),
+            couple_attempts: HashMap::new(),
         }
     }
 
+    /// Records every proposal, acceptance, rejection and displacement in `events` from the
+    /// next match on, to explain afterwards why applicants were or weren't placed where they
+    /// ranked (see `audit::explain`).
+    pub fn record_events(&mut self) {
+        self.events.keep();
+    }
+
+    /// Sends every event to `sink` as it happens, from the next match on. A sink should be
+    /// given one match, and `finish_events` called once it is over.
+    pub fn stream_events(&mut self, sink: impl EventSink + 'static) {
+        self.events.send_to(Box::new(sink));
+    }
+
+    /// Finishes with the sink given to `stream_events`, returning any error it had.
+    pub fn finish_events(&mut self) -> bincode::Result<()> {
+        self.events.finish()
+    }
+
     fn clear(&mut self) {
         self.matches.clear();
         self.unmatched_a.clear();
         self.unmatched_p.clear();
+        self.instability = InstabilityReport::default();
+        self.linked.clear();
+        self.events.clear();
+        self.couple_attempts.clear();
     }
 
     fn attempt_single_match(&mut self, applicant: &'a A) -> Result<(), MatchError>
     {
         // assumes applicant is not couples-matching
         assert!(applicant.get_couple().is_none());
-        let ranking = applicant.ranking();
+        match self.propose(applicant, &applicant.ranking(), false)? {
+            Some(program_id) => self.attempt_supplemental(applicant, program_id, None),
+            None => {
+                // unmatched applicant
+                self.events.record(|| MatchEvent::Exhausted { applicant: applicant.id() });
+                self.unmatched_a.push(&applicant);
+                Ok(())
+            }
+        }
+    }
+
+    /// Proposes to each program on `ranking` in turn until one tentatively takes the
+    /// applicant, returning that program. `linked` marks the place as a preliminary position
+    /// held alongside the applicant's advanced program.
+    fn propose(&mut self, applicant: &'a A, ranking: &[u32], linked: bool) -> Result<Option<u32>, MatchError>
+    {
         for program_id in ranking.iter() {
             let program = self.matches.iter_mut()
                 .find(|m| m.0.id() == *program_id)
@@ -60,15 +196,25 @@ where
 
             let program_ranking = program.0.ranking();
             let rank_position = program_ranking.iter().position(|&a| a == applicant.id());
+            let proposal = Proposal { applicant: applicant.id(), program: *program_id, paired_with: None, linked };
             if rank_position.is_none() || program.0.capacity() == 0 {
                 // if program did not rank applicant, try the next program
+                let reason = match rank_position {
+                    None => Rejection::NotRanked,
+                    Some(_) => Rejection::NoPositions
+                };
+                self.events.record(|| MatchEvent::Rejected { proposal, reason });
                 continue;
             }
-            if program.0.capacity() > program.1.len() as u8 {
+            if program.0.capacity() > program.1.len() as u16 {
                 // if program has an opening, tentatively match applicant to program
                 program.1.push(&applicant);
-                assert!(program.1.len() as u8 <= program.0.capacity());
-                return Ok(());
+                self.events.record(|| MatchEvent::Accepted { proposal, rank: rank_position.unwrap() });
+                assert!(program.1.len() as u16 <= program.0.capacity());
+                if linked {
+                    self.linked.insert(applicant.id(), *program_id);
+                }
+                return Ok(Some(*program_id));
             }
             let rank_map: Vec<(usize, usize)> = program.1.iter().enumerate().map(|a| (
                 a.0, // tentative match index
@@ -85,28 +231,90 @@ where
                 // then applicant takes their spot & we re-attempt the displaced applicant
                 let weakest_applicant: &A = program.1.swap_remove(*weakest_index);
                 program.1.push(&applicant);
-                assert!(program.1.len() as u8 <= program.0.capacity());
-                return match weakest_applicant.get_couple() {
-                    None => self.retry_match(weakest_applicant),
-                    Some(c) => {
-                        if let Some(i) = program.1.iter().position(|a| a.id() == c) {
-                            // skip some work and retry couple directly
-                            let weakest_applicant_couple = program.1.swap_remove(i);
-                            self.attempt_couples_match(weakest_applicant, Some(weakest_applicant_couple))
-                        } else {
-                            // sorry mario, your princess is another castle
-                            // (go find her)
-                            self.retry_match(weakest_applicant)
-                        }
-                    }
+                assert!(program.1.len() as u16 <= program.0.capacity());
+                let partner = weakest_applicant.get_couple()
+                    .and_then(|c| program.1.iter().position(|a| a.id() == c))
+                    .map(|i| program.1.swap_remove(i));
+                self.events.record(|| MatchEvent::Displaced {
+                    applicant: weakest_applicant.id(), program: *program_id, by: applicant.id(),
+                    rank: *weakest_rank, by_rank: rank_position.unwrap()
+                });
+                if let Some(partner) = partner {
+                    self.events.record(|| MatchEvent::Withdrawn { applicant: partner.id(), program: *program_id });
                 }
+                self.events.record(|| MatchEvent::Accepted { proposal, rank: rank_position.unwrap() });
+                // the applicant holds the place before anyone is re-attempted, in case the
+                // displaced applicant goes on to displace them
+                if linked {
+                    self.linked.insert(applicant.id(), *program_id);
+                }
+                match partner {
+                    // skip some work and retry couple directly
+                    Some(weakest_applicant_couple) => self.attempt_couples_match(weakest_applicant, Some(weakest_applicant_couple))?,
+                    // sorry mario, your princess is another castle
+                    // (go find her)
+                    None => self.retry_match(weakest_applicant)?
+                }
+                return Ok(Some(*program_id));
             }
+            self.events.record(|| MatchEvent::Rejected {
+                proposal,
+                reason: Rejection::Full { rank: rank_position.unwrap(), weakest_rank: *weakest_rank }
+            });
+        }
+        Ok(None)
+    }
+
+    /// Holds a preliminary position for an applicant just placed with an advanced program,
+    /// from their supplemental rank list for it, carrying on down the list after `after`
+    /// (the preliminary program they just lost) if given. Applicants keep the advanced
+    /// program even if no preliminary program takes them.
+    ///
+    /// Couples match on their joint rank list alone, so only single applicants' supplemental
+    /// rank lists are used.
+    fn attempt_supplemental(&mut self, applicant: &'a A, advanced: u32, after: Option<u32>) -> Result<(), MatchError>
+    {
+        let supplemental = match applicant.supplemental(advanced) {
+            None => return Ok(()),
+            Some(s) => s
+        };
+        // the applicant may have been displaced again and moved on while re-attempting others
+        if self.linked.contains_key(&applicant.id()) || !self.holds(advanced, applicant.id()) {
+            return Ok(());
         }
-        // unmatched applicant
-        self.unmatched_a.push(&applicant);
+        let start = after
+            .and_then(|p| supplemental.iter().position(|q| *q == p))
+            .map_or(0, |i| i + 1);
+        self.propose(applicant, &supplemental[start..], true)?;
         Ok(())
     }
 
+    fn holds(&self, program_id: u32, applicant_id: u32) -> bool {
+        self.matches.iter().any(|m| m.0.id() == program_id && m.1.iter().any(|a| a.id() == applicant_id))
+    }
+
+    /// Re-attempts a single applicant displaced from a program. With a preliminary position
+    /// linked to their advanced program, they either lost the preliminary position and carry
+    /// on down their supplemental rank list, or lost the advanced program and give up the
+    /// preliminary position with it.
+    fn retry_single(&mut self, applicant: &'a A) -> Result<(), MatchError> {
+        if let Some(preliminary) = self.linked.remove(&applicant.id()) {
+            if self.holds(preliminary, applicant.id()) {
+                let program = self.matches.iter_mut().find(|m| m.0.id() == preliminary).unwrap();
+                let index = program.1.iter().position(|a| a.id() == applicant.id()).unwrap();
+                program.1.swap_remove(index);
+                self.events.record(|| MatchEvent::Withdrawn { applicant: applicant.id(), program: preliminary });
+            } else {
+                let advanced = self.matches.iter()
+                    .find(|m| m.1.iter().any(|a| a.id() == applicant.id()))
+                    .map(|m| m.0.id())
+                    .ok_or(MatchError::ProgramNotFound(format!("retry_single: advanced: applicant {} in matches.iter()", applicant.id())))?;
+                return self.attempt_supplemental(applicant, advanced, Some(preliminary));
+            }
+        }
+        self.attempt_single_match(applicant)
+    }
+
     fn attempt_couples_match(&mut self, applicant: &'a A, couple: Option<&'a A>) -> Result<(), MatchError>
     {
         let couple = match &couple {
@@ -114,6 +322,19 @@ where
             Some(b) => b
         };
         assert!(applicant.get_couple().is_some() && couple.get_couple().is_some());
+        let attempts = self.couple_attempts.entry(u32::min(applicant.id(), couple.id())).or_insert(0);
+        *attempts += 1;
+        if *attempts > MAX_COUPLE_ATTEMPTS {
+            let ids = (u32::min(applicant.id(), couple.id()), u32::max(applicant.id(), couple.id()));
+            if !self.instability.cycling_couples.contains(&ids) {
+                self.instability.cycling_couples.push(ids);
+            }
+            self.events.record(|| MatchEvent::GaveUp { applicant: applicant.id() });
+            self.events.record(|| MatchEvent::GaveUp { applicant: couple.id() });
+            self.unmatched_a.push(applicant);
+            self.unmatched_a.push(couple);
+            return Ok(());
+        }
         let ranking: Vec<(u32, u32)> = applicant.ranking().into_iter().zip(couple.ranking()).collect();
         for program_pair in ranking.iter() {
             let p0 = self.matches.iter()
@@ -132,14 +353,37 @@ where
             // position of each applicant on their respective program's ranklists
             let rank0 = p_ranks.0.iter().position(|&a| a == applicant.id());
             let rank1 = p_ranks.1.iter().position(|&a| a == couple.id());
+            let proposals = (
+                Proposal { applicant: applicant.id(), program: program_pair.0, paired_with: Some(program_pair.1), linked: false },
+                Proposal { applicant: couple.id(), program: program_pair.1, paired_with: Some(program_pair.0), linked: false }
+            );
+            // logs both partners being turned down, each for the reason their own program gives
+            let reject = |events: &mut EventLog, reasons: (Rejection, Rejection)| {
+                events.record(|| MatchEvent::Rejected { proposal: proposals.0, reason: reasons.0 });
+                events.record(|| MatchEvent::Rejected { proposal: proposals.1, reason: reasons.1 });
+            };
             if rank0.is_none() || p0.0.capacity() == 0
                 || rank1.is_none() || p1.0.capacity() == 0
                 || (same_program && p0.0.capacity() == 1) {
                 // if program did not rank applicant, try the next program
+                let capacity = |p: &P| if same_program && p.capacity() == 1 { 0 } else { p.capacity() };
+                reject(&mut self.events, (
+                    couple_rejection(rank0, capacity(p0.0), None),
+                    couple_rejection(rank1, capacity(p1.0), None)
+                ));
                 continue;
             }
             let rank0 = rank0.unwrap();
             let rank1 = rank1.unwrap();
+            // logs both partners being tentatively matched, after anyone they displaced
+            let accept = |events: &mut EventLog| {
+                events.record(|| MatchEvent::Accepted { proposal: proposals.0, rank: rank0 });
+                events.record(|| MatchEvent::Accepted { proposal: proposals.1, rank: rank1 });
+            };
+            let displace = |events: &mut EventLog, displaced: &A, program: u32, rank: usize, by: &A| {
+                let by_rank = if by.id() == applicant.id() { rank0 } else { rank1 };
+                events.record(|| MatchEvent::Displaced { applicant: displaced.id(), program, by: by.id(), rank, by_rank });
+            };
 
             // program 0's ranklist as (tentative-rank index, applicant rank)
             let mut r0_map: Vec<(usize, usize)> = p0.1.iter().enumerate().map(|a| (
@@ -157,87 +401,40 @@ where
             let mut r0_worst_iter = r0_map.iter();
 
             if same_program {
-                let available_spots = max(p0.0.capacity() - p0.1.len() as u8, 0);
+                let available_spots = max(p0.0.capacity() - p0.1.len() as u16, 0);
                 match available_spots {
                     0 => {
                         // if program has space for no applicants, replace the two weakest
                         // tentatively matched applicants to make room for both
-                        let (weak_index0, weak_rank) = r0_worst_iter.next().unwrap();
-                        if rank0 < *weak_rank && rank1 < *weak_rank {
-                            let (mut weak_index1, weak_rank) = r0_worst_iter.next().unwrap();
+                        let (weak_index0, weak_rank0) = r0_worst_iter.next().unwrap();
+                        let mut threshold = *weak_rank0;
+                        if rank0 < *weak_rank0 && rank1 < *weak_rank0 {
+                            let (weak_index1, weak_rank) = r0_worst_iter.next().unwrap();
+                            threshold = *weak_rank;
                             if rank0 < *weak_rank && rank1 < *weak_rank {
                                 // both applicants are preferred to both currently weakest matched applicants
                                 // we re-attempt both displaced applicants
                                 let p0 = self.matches.iter_mut()
                                     .find(|m| m.0.id() == program_pair.0)
                                     .ok_or(MatchError::ProgramNotFound(format!("couples: &mut p0 (1): *program_pair.0 {} in matches.iter()", program_pair.0)))?;
-                                let weakest_applicant0: &A = p0.1.swap_remove(*weak_index0);
-                                if *weak_index0 < weak_index1 {
-                                    // adjust index for second removal
-                                    weak_index1 = weak_index1 - 1;
-                                }
-                                let weakest_applicant1: &A = p0.1.swap_remove(weak_index1);
+                                // remove the later index first so swap_remove doesn't move the other
+                                let (first, second) = (usize::max(*weak_index0, *weak_index1), usize::min(*weak_index0, *weak_index1));
+                                let weakest_applicant0: &A = p0.1.swap_remove(first);
+                                let weakest_applicant1: &A = p0.1.swap_remove(second);
                                 p0.1.push(applicant);
                                 p0.1.push(couple);
-                                assert!(p0.1.len() as u8 <= p0.0.capacity());
-                                return match weakest_applicant0.get_couple() {
-                                    Some(c) => {
-                                        return if weakest_applicant0.get_couple().eq(&Some(weakest_applicant1.id())) {
-                                            // we displaced both partners of the couple
-                                            self.attempt_couples_match(weakest_applicant0, Some(weakest_applicant1))
-                                        } else if let Some(i) = p0.1.iter().position(|a| a.id() == c) {
-                                            // we displaced one partner of the couple (and a rando)
-                                            let extra = p0.1.swap_remove(i);
-                                            match weakest_applicant1.get_couple() {
-                                                None => self.retry_match(weakest_applicant1)?,
-                                                Some(j) => {
-                                                    if let Some(k) = p0.1.iter().position(|a| a.id() == j) {
-                                                        // the rando is a couple at the same program
-                                                        let extra2 = p0.1.swap_remove(k);
-                                                        self.attempt_couples_match(weakest_applicant1, Some(extra2))?
-                                                    } else {
-                                                        self.retry_match(weakest_applicant1)?
-                                                    }
-                                                }
-                                            };
-                                            self.attempt_couples_match(weakest_applicant0, Some(extra))
-                                        } else {
-                                            // neither displaced applicant is coupled at this same program
-                                            self.retry_match(weakest_applicant1)?;
-                                            self.retry_match(weakest_applicant0)
-                                        }
-                                    },
-                                    None => {
-                                        match weakest_applicant1.get_couple() {
-                                            None => self.retry_match(weakest_applicant1)?,
-                                            Some(c) => {
-                                                if let Some(i) = p0.1.iter().position(|a| a.id() == c) {
-                                                    // we displaced one partner of the couple (and a rando)
-                                                    let extra = p0.1.swap_remove(i);
-                                                    match weakest_applicant0.get_couple() {
-                                                        None => self.retry_match(weakest_applicant0)?,
-                                                        Some(j) => {
-                                                            if let Some(k) = p0.1.iter().position(|a| a.id() == j) {
-                                                                // the rando is a couple at the same program
-                                                                let extra2 = p0.1.swap_remove(k);
-                                                                self.attempt_couples_match(weakest_applicant0, Some(extra2))?
-                                                            } else {
-                                                                self.retry_match(weakest_applicant0)?
-                                                            }
-                                                        }
-                                                    };
-                                                    return self.attempt_couples_match(weakest_applicant1, Some(extra));
-                                                } else {
-                                                    // neither displaced applicant is coupled at this same program
-                                                    self.retry_match(weakest_applicant1)?
-                                                }
-                                            }
-                                        };
-                                        self.retry_match(weakest_applicant0)
-                                    }
-                                }
+                                assert!(p0.1.len() as u16 <= p0.0.capacity());
+                                let ranks = if first == *weak_index0 { (*weak_rank0, *weak_rank) } else { (*weak_rank, *weak_rank0) };
+                                displace(&mut self.events, weakest_applicant0, program_pair.0, ranks.0, applicant);
+                                displace(&mut self.events, weakest_applicant1, program_pair.0, ranks.1, couple);
+                                accept(&mut self.events);
+                                return self.retry_displaced(Some(weakest_applicant0), Some(weakest_applicant1));
                             }
                         }
+                        reject(&mut self.events, (
+                            couple_rejection(Some(rank0), p0.0.capacity(), Some(threshold)),
+                            couple_rejection(Some(rank1), p0.0.capacity(), Some(threshold))
+                        ));
                     },
                     1 => {
                         // if program has space for one applicant, tentatively match both
@@ -252,13 +449,18 @@ where
                             let weakest_applicant: &A = p0.1.swap_remove(*weak_index);
                             p0.1.push(applicant);
                             p0.1.push(couple);
-                            assert!(p0.1.len() as u8 <= p0.0.capacity());
+                            assert!(p0.1.len() as u16 <= p0.0.capacity());
+                            // whichever partner the program ranked lower needed the place
+                            let by = if rank0 > rank1 { applicant } else { couple };
+                            displace(&mut self.events, weakest_applicant, program_pair.0, *weak_rank, by);
+                            accept(&mut self.events);
                             return match weakest_applicant.get_couple() {
                                 None => self.retry_match(weakest_applicant),
                                 Some(c) => {
                                     if let Some(i) = p0.1.iter().position(|a| a.id() == c) {
                                         // skip some work and retry couple directly
                                         let weakest_applicant_couple = p0.1.swap_remove(i);
+                                        self.events.record(|| MatchEvent::Withdrawn { applicant: c, program: program_pair.0 });
                                         self.attempt_couples_match(weakest_applicant, Some(weakest_applicant_couple))
                                     } else {
                                         // sorry mario, your princess is another castle
@@ -268,9 +470,13 @@ where
                                 }
                             }
                         }
+                        reject(&mut self.events, (
+                            couple_rejection(Some(rank0), p0.0.capacity(), Some(*weak_rank)),
+                            couple_rejection(Some(rank1), p0.0.capacity(), Some(*weak_rank))
+                        ));
                     },
                     _ => {
-                        assert!(p0.0.capacity() - p0.1.len() as u8 >= 2,
+                        assert!(p0.0.capacity() - p0.1.len() as u16 >= 2,
                                 "couples: available_spots {} not >= 2; {} - {}",
                                 available_spots, p0.0.capacity(), p0.1.len()
                         );
@@ -280,7 +486,8 @@ where
                             .ok_or(MatchError::ProgramNotFound(format!("couples: &mut p0 (3): *program_pair.0 {} in matches.iter()", program_pair.0)))?;
                         p0.1.push(applicant);
                         p0.1.push(couple);
-                        assert!(p0.1.len() as u8 <= p0.0.capacity());
+                        assert!(p0.1.len() as u16 <= p0.0.capacity());
+                        accept(&mut self.events);
                         let test_p = self.matches.iter()
                             .find(|m| m.1.iter().any(|a| a.get_couple().eq(&Some(applicant.get_couple().unwrap()))));
                         assert!(&test_p.is_some(), "couples: program: any(couple) {} in matches.iter()", applicant.get_couple().unwrap());
@@ -289,77 +496,65 @@ where
                 };
             } else {
                 assert_ne!(p0.0.id(), p1.0.id());
-                if p0.0.capacity() > p0.1.len() as u8
-                    && p1.0.capacity() > p1.1.len() as u8 {
-                    // if programs both have an opening, tentatively match applicant to programs
-                    {
-                        let p0 = self.matches.iter_mut()
-                            .find(|m| m.0.id() == program_pair.0)
-                            .ok_or(MatchError::ProgramNotFound(format!("couples: &mut p0 (4): *program_pair.0 {} in matches.iter()", program_pair.0)))?;
-                        p0.1.push(applicant);
-                        assert!(p0.1.len() as u8 <= p0.0.capacity());
-                    }
-                    let p1 = self.matches.iter_mut()
-                        .find(|m| m.0.id() == program_pair.1)
-                        .ok_or(MatchError::ProgramNotFound(format!("couples: &mut p1 (1): *program_pair.0 {} in matches.iter()", program_pair.0)))?;
-                    p1.1.push(couple);
-                    assert!(p1.1.len() as u8 <= p1.0.capacity(), "couples: program {} p1.1.len() {} <= p1.0.capacity() {}", p1.0.id(), p1.1.len(), p1.0.capacity());
-                    let test_p = self.matches.iter()
-                        .find(|m| m.1.iter().any(|a| a.get_couple().eq(&Some(applicant.get_couple().unwrap()))));
-                    assert!(&test_p.is_some(), "couples: program: any(couple) {} in matches.iter()", applicant.get_couple().unwrap());
-                    return Ok(());
+                // weakest tentative match of each program, unless the program has an opening
+                let r0_worst = match p0.0.capacity() > p0.1.len() as u16 {
+                    true => None,
+                    false => r0_worst_iter.next().copied()
+                };
+                let r1_worst = match p1.0.capacity() > p1.1.len() as u16 {
+                    true => None,
+                    false => p1.1.iter().enumerate().map(|a| (
+                        a.0, // tentative match index
+                        p_ranks.1.iter()
+                            .position(|&b| b == a.1.id())
+                            .ok_or(MatchError::ApplicantNotFound(format!("couples: r1_worst: a.1.id() {} in p_ranks.1.iter()", a.1.id()))) // applicant rank index
+                    )).map(|(a, b)| match b {
+                        Ok(b) => Ok((a, b)),
+                        Err(e) => Err(e)
+                    }).collect::<Result<Vec<(usize, usize)>, MatchError>>()?
+                        .into_iter()
+                        .max_by(|a, b| a.1.cmp(&b.1))
+                };
+                if r0_worst.is_some_and(|(_, r)| rank0 > r) || r1_worst.is_some_and(|(_, r)| rank1 > r) {
+                    // if either program prefers its weakest tentative match, try the next pair
+                    reject(&mut self.events, (
+                        couple_rejection(Some(rank0), p0.0.capacity(), r0_worst.map(|(_, r)| r)),
+                        couple_rejection(Some(rank1), p1.0.capacity(), r1_worst.map(|(_, r)| r))
+                    ));
+                    continue;
                 }
 
-                // program 1's ranklist as (tentative-rank index, applicant rank)
-                let mut r1_map: Vec<(usize, usize)> = p1.1.iter().enumerate().map(|a| (
-                    a.0, // tentative match index
-                    p_ranks.1.iter()
-                        .position(|&b| b == a.1.id())
-                        .ok_or(MatchError::ApplicantNotFound(format!("couples: r1_map: a.1.id() {} in p_ranks.1.iter()", a.1.id()))) // applicant rank index
-                )).map(|(a, b)| match b {
-                    Ok(b) => Ok((a, b)),
-                    Err(e) => Err(e)
-                }).collect::<Result<Vec<(usize, usize)>, MatchError>>()?;
-                // sort by descending applicant rank
-                r1_map.sort_by(|a, b| b.1.cmp(&a.1));
-                // weakest tentative match
-                let (r0_worst_index, r0_worst_rank) = r0_worst_iter.next().unwrap();
-                let (mut r1_worst_index, r1_worst_rank) = r1_map.iter().next().unwrap();
-
-                if rank0 < *r0_worst_rank && rank1 < *r1_worst_rank {
-                    // if both applicants are preferred to both weakest tentatively matched applicants
-                    // we re-attempt the two displaced applicants
-                    let weakest_applicant0: &A;
-                    {
-                        let p0 = self.matches.iter_mut()
-                            .find(|m| m.0.id() == program_pair.0)
-                            .ok_or(MatchError::ProgramNotFound(format!("couples: &mut p0 (5): *program_pair.0 {} in matches.iter()", program_pair.0)))?;
-                        weakest_applicant0 = p0.1.swap_remove(*r0_worst_index);
-                        p0.1.push(applicant);
-                        assert!(p0.1.len() as u8 <= p0.0.capacity());
-                    }
-                    let p1 = self.matches.iter_mut()
-                        .find(|m| m.0.id() == program_pair.1)
-                        .ok_or(MatchError::ProgramNotFound(format!("couples: &mut p1 (2): *program_pair.0 {} in matches.iter()", program_pair.0)))?;
-                    if *r0_worst_index < r1_worst_index {
-                        // adjust index for second removal
-                        r1_worst_index = r1_worst_index - 1;
-                    }
-                    let weakest_applicant1: &A = p1.1.swap_remove(r1_worst_index);
-                    p1.1.push(couple);
-                    assert!(p1.1.len() as u8 <= p1.0.capacity());
-                    let test_p = self.matches.iter()
-                        .find(|m| m.1.iter().any(|a| a.get_couple().eq(&Some(applicant.get_couple().unwrap()))));
-                    assert!(&test_p.is_some(), "couples: program: any(couple) {} in matches.iter()", applicant.get_couple().unwrap());
-
-                    self.retry_match(weakest_applicant0)?;
-                    self.retry_match(weakest_applicant1)?;
-
-                    return Ok(());
+                // both programs have an opening or prefer the applicants to their weakest tentative matches,
+                // so tentatively match the applicants & re-attempt anyone they displaced
+                let weakest_applicant0: Option<&A>;
+                {
+                    let p0 = self.matches.iter_mut()
+                        .find(|m| m.0.id() == program_pair.0)
+                        .ok_or(MatchError::ProgramNotFound(format!("couples: &mut p0 (4): *program_pair.0 {} in matches.iter()", program_pair.0)))?;
+                    weakest_applicant0 = r0_worst.map(|(i, _)| p0.1.swap_remove(i));
+                    p0.1.push(applicant);
+                    assert!(p0.1.len() as u16 <= p0.0.capacity());
                 }
+                let p1 = self.matches.iter_mut()
+                    .find(|m| m.0.id() == program_pair.1)
+                    .ok_or(MatchError::ProgramNotFound(format!("couples: &mut p1 (1): *program_pair.1 {} in matches.iter()", program_pair.1)))?;
+                let weakest_applicant1 = r1_worst.map(|(i, _)| p1.1.swap_remove(i));
+                p1.1.push(couple);
+                assert!(p1.1.len() as u16 <= p1.0.capacity(), "couples: program {} p1.1.len() {} <= p1.0.capacity() {}", p1.0.id(), p1.1.len(), p1.0.capacity());
+                if let (Some(a), Some((_, rank))) = (weakest_applicant0, r0_worst) {
+                    displace(&mut self.events, a, program_pair.0, rank, applicant);
+                }
+                if let (Some(a), Some((_, rank))) = (weakest_applicant1, r1_worst) {
+                    displace(&mut self.events, a, program_pair.1, rank, couple);
+                }
+                accept(&mut self.events);
+
+                return self.retry_displaced(weakest_applicant0, weakest_applicant1);
             }
         }
 
+        self.events.record(|| MatchEvent::Exhausted { applicant: applicant.id() });
+        self.events.record(|| MatchEvent::Exhausted { applicant: couple.id() });
         self.unmatched_a.push(applicant);
         self.unmatched_a.push(couple);
         Ok(())
@@ -367,22 +562,69 @@ where
 
     fn retry_match(&mut self, applicant: &'a A) -> Result<(), MatchError> {
         match applicant.get_couple() {
-            None => self.attempt_single_match(applicant),
+            None => self.retry_single(applicant),
             Some(couple) => {
-                let program = self.matches.iter_mut()
-                    .find(|m| m.1.iter().any(|a| a.get_couple().eq(&Some(couple))));
-                assert!(&program.is_some(), "retry: program: any(couple) {} in matches.iter()", couple);
-                let program = program.unwrap();
-                let index = program.1.iter()
-                    .position(|a| a.get_couple() == Some(couple))
-                    .ok_or(MatchError::ApplicantNotFound(format!("retry: index: couple {} in program.1.iter()", couple)))?;
-                let couple = program.1.swap_remove(index);
+                let couple = self.withdraw(couple)?;
                 self.attempt_couples_match(applicant, Some(couple))
             }
         }
     }
 
+    /// Removes a tentatively matched applicant from their program, along with any
+    /// preliminary position linked to it.
+    fn withdraw(&mut self, applicant_id: u32) -> Result<&'a A, MatchError> {
+        let linked = self.linked.remove(&applicant_id);
+        if let Some(preliminary) = linked {
+            let program = self.matches.iter_mut().find(|m| m.0.id() == preliminary).unwrap();
+            if let Some(index) = program.1.iter().position(|a| a.id() == applicant_id) {
+                program.1.swap_remove(index);
+                self.events.record(|| MatchEvent::Withdrawn { applicant: applicant_id, program: preliminary });
+            }
+        }
+        let program = self.matches.iter_mut()
+            .find(|m| m.1.iter().any(|a| a.id() == applicant_id));
+        assert!(&program.is_some(), "withdraw: program: any(applicant) {} in matches.iter()", applicant_id);
+        let program = program.unwrap();
+        let index = program.1.iter()
+            .position(|a| a.id() == applicant_id)
+            .ok_or(MatchError::ApplicantNotFound(format!("withdraw: index: applicant {} in program.1.iter()", applicant_id)))?;
+        self.events.record(|| MatchEvent::Withdrawn { applicant: applicant_id, program: program.0.id() });
+        Ok(program.1.swap_remove(index))
+    }
+
+    /// Re-attempts up to two applicants displaced by the same couple.
+    ///
+    /// Partners of the displaced applicants are withdrawn before anyone is re-attempted, so
+    /// no one is left tentatively matched while their partner is waiting to be re-attempted.
+    fn retry_displaced(&mut self, displaced0: Option<&'a A>, displaced1: Option<&'a A>) -> Result<(), MatchError> {
+        if let (Some(a), Some(b)) = (displaced0, displaced1) {
+            if a.get_couple() == Some(b.id()) {
+                // we displaced both partners of a couple
+                return self.attempt_couples_match(a, Some(b));
+            }
+        }
+        let mut retries = Vec::new();
+        for a in displaced0.into_iter().chain(displaced1) {
+            let couple = match a.get_couple() {
+                None => None,
+                Some(c) => Some(self.withdraw(c)?)
+            };
+            retries.push((a, couple));
+        }
+        for (a, couple) in retries {
+            match couple {
+                None => self.retry_single(a)?,
+                Some(_) => self.attempt_couples_match(a, couple)?
+            }
+        }
+        Ok(())
+    }
+
     pub fn run_match(&mut self, a: &'a Vec<Couple<A>>, p: &'a Vec<P>) -> Result<(), MatchError> {
+        self.run_match_(a, p, InstabilityResolution::default())
+    }
+
+    pub fn run_match_(&mut self, a: &'a Vec<Couple<A>>, p: &'a Vec<P>, resolution: InstabilityResolution) -> Result<(), MatchError> {
         self.clear();
         self.matches = p.into_iter().map(|p| (p, Vec::new())).collect();
 
@@ -405,10 +647,274 @@ where
             .into_iter().collect::<Result<Vec<()>, MatchError>>()?;
         stdout.execute(cursor::Show).unwrap();
 
+        // an applicant displaced from their advanced program gives up their preliminary
+        // position too, which can open a place someone was turned away from earlier
+        let linked = a.iter().any(|c| c.0.has_supplemental() || c.1.as_ref().is_some_and(|b| b.has_supplemental()));
+        if resolution == InstabilityResolution::Reprocess || linked {
+            self.reprocess(a, p)?;
+        }
+        self.instability.blocking_pairs = stability::blocking_pairs(a, p, self);
+        self.finalize();
+        Ok(())
+    }
+
+    /// Puts applicants in blocking pairs back into the match (in the order they applied)
+    /// until there are no blocking pairs, a result comes up twice, or we run out of rounds.
+    /// A result coming up twice only means that order is going in circles, so they are then
+    /// put back in the reverse order, and it takes a result coming up twice in that order too
+    /// for the match to be cycling.
+    fn reprocess(&mut self, a: &'a [Couple<A>], p: &'a [P]) -> Result<(), MatchError> {
+        let mut seen = HashSet::new();
+        let mut reversed = false;
+        while self.instability.rounds < MAX_REPROCESSING_ROUNDS {
+            let blocking: HashSet<u32> = stability::blocking_pairs(a, p, self).iter()
+                .map(|b| match b {
+                    BlockingPair::Single { applicant, .. } | BlockingPair::Supplemental { applicant, .. } => *applicant,
+                    BlockingPair::Couple { applicants, .. } => applicants.0
+                })
+                .collect();
+            if blocking.is_empty() {
+                break;
+            }
+            if !seen.insert((self.fingerprint(), reversed)) {
+                if reversed {
+                    self.instability.repeated = true;
+                    break;
+                }
+                reversed = true;
+                seen.insert((self.fingerprint(), reversed));
+            }
+            self.instability.rounds += 1;
+            self.couple_attempts.clear();
+            let mut units: Vec<&'a Couple<A>> = a.iter().filter(|c| blocking.contains(&c.0.id())).collect();
+            if reversed {
+                units.reverse();
+            }
+            for c in units {
+                for m in std::iter::once(&c.0).chain(&c.1) {
+                    self.events.record(|| MatchEvent::Reentered { applicant: m.id() });
+                }
+                self.remove(c.0.id())?;
+                if let Some(b) = &c.1 {
+                    self.remove(b.id())?;
+                }
+                self.attempt_couples_match(&c.0, c.1.as_ref())?;
+            }
+        }
+        // a couple given up on can still match once put back into the match
+        let unmatched: HashSet<u32> = self.unmatched_a.iter().map(|a| a.id()).collect();
+        self.instability.cycling_couples.retain(|(a, _)| unmatched.contains(a));
+        Ok(())
+    }
+
+    /// Takes an applicant out of the match, wherever they are.
+    fn remove(&mut self, applicant_id: u32) -> Result<(), MatchError> {
+        if let Some(i) = self.unmatched_a.iter().position(|a| a.id() == applicant_id) {
+            self.unmatched_a.remove(i);
+        } else if self.matches.iter().any(|m| m.1.iter().any(|a| a.id() == applicant_id)) {
+            self.withdraw(applicant_id)?;
+        }
+        Ok(())
+    }
+
+    /// Identifies the current tentative matches, to tell when reprocessing is going in circles.
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (p, matched) in self.matches.iter() {
+            let mut ids: Vec<u32> = matched.iter().map(|a| a.id()).collect();
+            ids.sort_unstable();
+            (p.id(), ids).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    pub fn rematch(&mut self, a: &'a Vec<Couple<A>>, p: &'a Vec<P>, previous: &Assignment, delta: &Delta) -> Result<(), MatchError> {
+        self.rematch_(a, p, previous, delta, InstabilityResolution::default())
+    }
+
+    /// Catches the outcome of a previous match, `previous`, up with `delta` (already applied to
+    /// `a` and `p`), by re-running only the rejection chains the change sets off.
+    ///
+    /// Everyone is put back where they were, unless the change means they no longer can be.
+    /// Anyone who might now get into a program that gained an opening (or changed its mind)
+    /// is then put back into the match, which can open up places at the programs they leave,
+    /// and so on until no program has an opening anyone wants. Without couples the result is
+    /// then moved up to the applicant-optimal matching, so it is the same as matching from scratch.
+    pub fn rematch_(&mut self, a: &'a Vec<Couple<A>>, p: &'a Vec<P>, previous: &Assignment, delta: &Delta,
+                    resolution: InstabilityResolution) -> Result<(), MatchError> {
+        // preliminary positions linked to an advanced program are not in `previous`, so
+        // there is nothing to catch up from, and a log of events has to start from scratch
+        if self.events.is_on() || a.iter().any(|c| c.0.has_supplemental() || c.1.as_ref().is_some_and(|b| b.has_supplemental())) {
+            return self.run_match_(a, p, resolution);
+        }
+        self.clear();
+        self.matches = p.iter().map(|p| (p, Vec::new())).collect();
+        let programs: HashMap<u32, usize> = self.matches.iter().enumerate().map(|(i, m)| (m.0.id(), i)).collect();
+        let units: HashMap<u32, usize> = a.iter().enumerate()
+            .flat_map(|(i, c)| std::iter::once(&c.0).chain(&c.1).map(move |m| (m.id(), i)))
+            .collect();
+
+        let mut dirty: HashSet<u32> = HashSet::new();
+        let mut reenter: HashSet<usize> = HashSet::new();
+        match delta {
+            Delta::Capacity { program, .. } | Delta::ProgramRanking { program, .. } => {
+                dirty.insert(*program);
+            },
+            Delta::Withdrawal { applicant } => dirty.extend(previous.get(applicant)),
+            Delta::ApplicantRanking { applicant, .. } => reenter.extend(units.get(applicant))
+        }
+
+        // put everyone back where they were, if both sides still rank each other
+        for (i, c) in a.iter().enumerate() {
+            let members: Vec<&'a A> = std::iter::once(&c.0).chain(&c.1).collect();
+            let placed: Option<Vec<usize>> = members.iter()
+                .map(|m| previous.get(&m.id()).and_then(|p| programs.get(p).copied()))
+                .collect();
+            if reenter.contains(&i) {
+                dirty.extend(members.iter().filter_map(|m| previous.get(&m.id())));
+            } else if members.iter().all(|m| !previous.contains_key(&m.id())) {
+                self.unmatched_a.extend(members);
+            } else if placed.as_ref().is_some_and(|placed| self.still_ranked(&members, placed)) {
+                for (m, pi) in members.iter().zip(placed.unwrap()) {
+                    self.matches[pi].1.push(m);
+                }
+            } else {
+                dirty.extend(members.iter().filter_map(|m| previous.get(&m.id())));
+                reenter.insert(i);
+            }
+        }
+        // programs with fewer positions than before let their weakest applicants go
+        for m in self.matches.iter_mut() {
+            let ranking = m.0.ranking();
+            while m.1.len() > m.0.capacity() as usize {
+                let (weakest, _) = m.1.iter().enumerate()
+                    .max_by_key(|(_, h)| ranking.iter().position(|r| *r == h.id()))
+                    .unwrap();
+                let weakest = m.1.swap_remove(weakest);
+                reenter.insert(units[&weakest.id()]);
+            }
+        }
+
+        let mut ranked_by: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, c) in a.iter().enumerate() {
+            for program in std::iter::once(&c.0).chain(&c.1).flat_map(|m| m.ranking()) {
+                let units = ranked_by.entry(program).or_default();
+                if units.last() != Some(&i) {
+                    units.push(i);
+                }
+            }
+        }
+        loop {
+            let placement = stability::assignment(self);
+            for program in dirty.drain() {
+                for i in ranked_by.get(&program).into_iter().flatten() {
+                    if !reenter.contains(i) && self.would_move(&a[*i], program, &placement) {
+                        reenter.insert(*i);
+                    }
+                }
+            }
+            if reenter.is_empty() {
+                break;
+            }
+
+            // everyone re-entering is out of the way before anyone proposes, so nobody is
+            // turned away for someone about to leave
+            let mut order: Vec<usize> = reenter.drain().collect();
+            order.sort_unstable();
+            for c in order.iter().map(|i| &a[*i]) {
+                for m in std::iter::once(&c.0).chain(&c.1) {
+                    self.remove(m.id())?;
+                }
+            }
+            for c in order.iter().map(|i| &a[*i]) {
+                self.attempt_couples_match(&c.0, c.1.as_ref())?;
+            }
+            // programs that lost someone may have an opening for someone else
+            let current = stability::assignment(self);
+            dirty.extend(placement.iter().filter(|(a, p)| current.get(a) != Some(p)).map(|(_, p)| *p));
+        }
+
+        // without couples the chains above settle on a stable matching, though not always the
+        // one a full match would find, and the applicant-optimal matching can be reached from it
+        if a.iter().all(|c| c.1.is_none()) {
+            self.reprocess(a, p)?;
+            let settled = stability::assignment(self);
+            match stability::blocking_pairs(a, p, self).is_empty() {
+                true => match lattice::applicant_optimal_from(a, p, &settled) {
+                    Ok(optimal) => self.place(a, &optimal),
+                    Err(_) => return self.run_match_(a, p, resolution)
+                },
+                false => return self.run_match_(a, p, resolution)
+            }
+            self.instability = InstabilityReport::default();
+        }
+
+        if resolution == InstabilityResolution::Reprocess {
+            self.reprocess(a, p)?;
+        }
+        self.instability.blocking_pairs = stability::blocking_pairs(a, p, self);
         self.finalize();
         Ok(())
     }
 
+    /// Puts every applicant where `assignment` says, and everyone it leaves out in the unmatched.
+    fn place(&mut self, a: &'a [Couple<A>], assignment: &Assignment) {
+        let programs: HashMap<u32, usize> = self.matches.iter().enumerate().map(|(i, m)| (m.0.id(), i)).collect();
+        for m in self.matches.iter_mut() {
+            m.1.clear();
+        }
+        self.unmatched_a.clear();
+        for m in a.iter().flat_map(|c| std::iter::once(&c.0).chain(&c.1)) {
+            match assignment.get(&m.id()).and_then(|p| programs.get(p)) {
+                Some(pi) => self.matches[*pi].1.push(m),
+                None => self.unmatched_a.push(m)
+            }
+        }
+    }
+
+    /// Whether the programs of a previous placement still rank the applicants they hold and
+    /// the applicants still rank them (for a couple, as a pair on their joint rank list).
+    fn still_ranked(&self, members: &[&'a A], placed: &[usize]) -> bool {
+        let programs: Vec<u32> = placed.iter().map(|pi| self.matches[*pi].0.id()).collect();
+        let ranked = members.iter().zip(placed)
+            .all(|(m, pi)| self.matches[*pi].0.ranking().contains(&m.id()));
+        ranked && match members {
+            [single] => single.ranking().contains(&programs[0]),
+            [first, second] => first.ranking().into_iter().zip(second.ranking()).any(|pair| pair == (programs[0], programs[1])),
+            _ => false
+        }
+    }
+
+    /// Whether a single applicant or couple might do better with `program`: one of them
+    /// prefers it to where they are, and it ranks them above someone it holds (or has an opening).
+    fn would_move(&self, c: &Couple<A>, program: u32, placement: &Assignment) -> bool {
+        let m = &self.matches.iter().find(|m| m.0.id() == program).unwrap();
+        let ranking = m.0.ranking();
+        let worst = m.1.iter().filter_map(|h| ranking.iter().position(|r| *r == h.id())).max();
+        let opening = m.1.len() < m.0.capacity() as usize;
+        let accepts = |a: &A| match ranking.iter().position(|r| *r == a.id()) {
+            None => false,
+            Some(rank) => opening || worst.is_some_and(|w| rank < w)
+        };
+        match &c.1 {
+            None => {
+                let ranking = c.0.ranking();
+                let current = placement.get(&c.0.id())
+                    .and_then(|p| ranking.iter().position(|r| r == p))
+                    .unwrap_or(ranking.len());
+                accepts(&c.0) && ranking[..current].contains(&program)
+            },
+            Some(b) => {
+                let joint: Vec<(u32, u32)> = c.0.ranking().into_iter().zip(b.ranking()).collect();
+                let current = match (placement.get(&c.0.id()), placement.get(&b.id())) {
+                    (Some(p), Some(q)) => joint.iter().position(|r| *r == (*p, *q)).unwrap_or(joint.len()),
+                    _ => joint.len()
+                };
+                joint[..current].iter().any(|(p, q)| (*p == program && accepts(&c.0)) || (*q == program && accepts(b)))
+            }
+        }
+    }
+
     fn finalize(&mut self) {
         self.unmatched_p = self.matches.iter()
             .filter(|m| m.1.len() == 0)
@@ -1,10 +1,35 @@
+use std::cell::RefCell;
+use chrono::{DateTime, Duration, Utc};
 use rand;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::models::{SchoolType, Specialty};
+
+/// Fraction of applicants that apply as a couple.
+pub const COUPLE_RATE: f64 = 0.02;
+
+/// Fraction of programs that certify their rank list after the deadline.
+pub const LATE_CERTIFICATION_RATE: f64 = 0.01;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(rand::rng().random()));
+}
+
+/// Reseeds this thread's generator, so that the same seed samples the same population.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+fn with_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
 
-/*
 fn random_string(n: usize) -> String {
-    let chars: Vec<char> = (0..n).map(|_| rand::thread_rng().gen_range(65..91) as u8 as char).collect();
-    chars.iter().collect()
+    with_rng(|rng| (0..n).map(|_| rng.random_range(b'a'..=b'z') as char).collect())
+}
+
+fn random_digits(n: usize) -> String {
+    with_rng(|rng| (0..n).map(|_| rng.random_range(b'0'..=b'9') as char).collect())
 }
 
 pub fn random_email() -> String {
@@ -12,11 +37,11 @@ pub fn random_email() -> String {
 }
 
 pub fn random_phone() -> String {
-    format!("{}-{}-{}", random_string(3), random_string(3), random_string(4))
+    format!("{}-{}-{}", random_digits(3), random_digits(3), random_digits(4))
 }
 
 pub fn random_age() -> u8 {
-    rand::thread_rng().gen_range(25..=35)
+    with_rng(|rng| rng.random_range(25..=35))
 }
 
 pub fn random_name() -> String {
@@ -24,24 +49,84 @@ pub fn random_name() -> String {
     let rest = random_string(6);
     format!("{}{}", first, rest)
 }
-*/
 
-pub fn random_capacity() -> u8 {
-    rand::thread_rng().gen_range(1..=10)
+/// Picks a school type, weighted roughly by its share of applicants.
+pub fn random_school_type() -> SchoolType {
+    let weights = [45, 20, 15, 20];
+    let mut pick = with_rng(|rng| rng.random_range(0..weights.iter().sum::<u32>()));
+    for (school, weight) in SchoolType::ALL.iter().zip(weights) {
+        if pick < weight {
+            return *school;
+        }
+        pick -= weight;
+    }
+    unreachable!()
+}
+
+/// An exam score between 200 and 280 that rises with competitiveness, give or take 10 points.
+pub fn random_exam_score(competitiveness: f32) -> u16 {
+    let noise = with_rng(|rng| rng.random_range(-10.0f32..=10.0));
+    (240.0 + (competitiveness - 0.5) * 60.0 + noise).clamp(200.0, 280.0) as u16
+}
+
+/// Whether an applicant from the given school needs a visa: most graduates of schools
+/// outside the US who aren't citizens or residents do, and no one else does.
+pub fn needs_visa(school: SchoolType) -> bool {
+    school == SchoolType::NonUsImg && with_rng(|rng| rng.random_bool(0.8))
+}
+
+pub fn random_capacity() -> u16 {
+    with_rng(|rng| rng.random_range(1..=10))
 }
 
 pub fn random_competitiveness() -> f32 {
-    rand::thread_rng().gen_range(0f32..1f32)
+    with_rng(|rng| rng.random_range(0f32..1f32))
+}
+
+/// Picks a specialty, weighted roughly by its share of first-year residency positions.
+pub fn random_specialty() -> Specialty {
+    let weights = [30, 15, 10, 10, 8, 12, 8, 7];
+    let mut pick = with_rng(|rng| rng.random_range(0..weights.iter().sum::<u32>()));
+    for (specialty, weight) in Specialty::ALL.iter().zip(weights) {
+        if pick < weight {
+            return *specialty;
+        }
+        pick -= weight;
+    }
+    unreachable!()
 }
 
-/*
+pub fn seeded_rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+pub fn random_fit(rng: &mut StdRng, noise: f32) -> f32 {
+    if noise <= 0.0 {
+        return 0.0;
+    }
+    rng.random_range(-noise..=noise)
+}
+
+/// A rank list certification deadline 14 to 90 days into the season.
 pub fn random_deadline() -> DateTime<Utc> {
-    chrono::DateTime::default().add(
-        chrono::Duration::days(rand::thread_rng().gen_range(14..=90))
-    )
+    DateTime::default() + Duration::days(with_rng(|rng| rng.random_range(14..=90)))
+}
+
+/// When a program certifies its rank list: usually in the month before `deadline`, but
+/// now and then up to three days after it.
+pub fn random_certification(deadline: DateTime<Utc>) -> DateTime<Utc> {
+    with_rng(|rng| match rng.random_bool(LATE_CERTIFICATION_RATE) {
+        true => deadline + Duration::hours(rng.random_range(1..=72)),
+        false => deadline - Duration::hours(rng.random_range(0..=30 * 24))
+    })
 }
-*/
 
-pub fn is_coupled() -> bool {
-    rand::thread_rng().gen_bool(0.02)
-}
\ No newline at end of file
+pub fn random_institution() -> String {
+    let kinds = ["University Hospital", "Medical Center", "Memorial Hospital", "Health System"];
+    let kind = with_rng(|rng| kinds[rng.random_range(0..kinds.len())]);
+    format!("{} {}", random_name(), kind)
+}
+
+pub fn is_coupled(couple_rate: f64) -> bool {
+    with_rng(|rng| rng.random_bool(couple_rate))
+}
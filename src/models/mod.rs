@@ -1,8 +1,10 @@
-mod generator;
+pub mod generator;
 
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use crate::ranker::{Competitive, Rankable, ReceiveApplication};
+use crate::ranker::{Competitive, ProgramRankPolicy, Rankable, ReceiveApplication};
 
 static APPLICANT_COUNTER: AtomicU32 = AtomicU32::new(0);
 static PROGRAM_COUNTER: AtomicU32 = AtomicU32::new(0);
@@ -11,80 +13,204 @@ pub trait HasCouple {
     fn get_couple(&self) -> Option<u32>;
 }
 
+pub trait HasSupplemental {
+    /// Preliminary programs ranked for the year before `advanced`, most wanted first, if
+    /// the applicant made a supplemental rank list for it.
+    fn supplemental(&self, advanced: u32) -> Option<Vec<u32>>;
+    fn has_supplemental(&self) -> bool;
+}
+
+pub trait HasProfile {
+    fn profile(&self) -> Option<&Profile>;
+}
+
+pub trait HasPositionType {
+    fn position_type(&self) -> PositionType;
+}
+
 pub trait HasApplications {
-    fn applications(&self) -> u8;
+    fn applications(&self) -> u16;
 }
 
 pub trait HasCapacity {
-    fn capacity(&self) -> u8;
+    fn capacity(&self) -> u16;
 }
-/*
-#[derive(Clone)]
+
+pub trait HasSpecialty {
+    fn specialty(&self) -> Specialty;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Specialty {
+    InternalMedicine,
+    FamilyMedicine,
+    Pediatrics,
+    EmergencyMedicine,
+    Psychiatry,
+    Surgery,
+    Anesthesiology,
+    ObstetricsGynecology,
+}
+
+impl Specialty {
+    pub const ALL: [Specialty; 8] = [
+        Specialty::InternalMedicine,
+        Specialty::FamilyMedicine,
+        Specialty::Pediatrics,
+        Specialty::EmergencyMedicine,
+        Specialty::Psychiatry,
+        Specialty::Surgery,
+        Specialty::Anesthesiology,
+        Specialty::ObstetricsGynecology,
+    ];
+}
+/// Categorical programs train residents for their whole residency. Advanced programs start
+/// a year later, so applicants matched to one also need a preliminary (one-year) position,
+/// which they rank on a supplemental rank list for that advanced program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PositionType {
+    #[default]
+    Categorical,
+    Preliminary,
+    Advanced,
+}
+/// How far an applicant got on the way through the match, in the order the stages are
+/// reached. An applicant who stops short of `Certified` didn't take part in the match, and
+/// their status is where they fell out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum MatchStatus {
-    UNMATCHED, MATCHED, PENDING
+    #[default]
+    Registered,
+    /// Sent at least one application.
+    Applied,
+    /// Was interviewed by at least one program.
+    Interviewed,
+    /// Submitted a rank list.
+    Ranked,
+    /// Their rank list went into the match.
+    Certified,
+    Matched,
+    /// Took part in the match, but didn't match.
+    Unmatched,
+    /// Left the match before it ran.
+    Withdrawn,
+}
+/// Where an applicant went to medical school.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SchoolType {
+    /// A US allopathic (MD) school.
+    UsMd,
+    /// A US osteopathic (DO) school.
+    UsDo,
+    /// An international medical graduate who is a US citizen or resident.
+    UsImg,
+    /// An international medical graduate who is not.
+    NonUsImg,
+}
+
+impl SchoolType {
+    pub const ALL: [SchoolType; 4] = [SchoolType::UsMd, SchoolType::UsDo, SchoolType::UsImg, SchoolType::NonUsImg];
+}
+
+/// Who an applicant is, beyond what the match itself uses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub age: u8,
+    pub email: String,
+    pub phone: String,
+    pub school: SchoolType,
+    /// Licensing exam score, on a 3-digit scale.
+    pub exam_score: u16,
+    /// Whether the applicant needs a visa sponsored to train.
+    pub visa: bool,
+}
+
+impl Profile {
+    /// A profile for an applicant of the given competitiveness, whose exam score tracks it.
+    pub fn sample_profile(competitiveness: f32) -> Profile {
+        let school = generator::random_school_type();
+        Profile {
+            name: generator::random_name(),
+            age: generator::random_age(),
+            email: generator::random_email(),
+            phone: generator::random_phone(),
+            school,
+            exam_score: generator::random_exam_score(competitiveness),
+            visa: generator::needs_visa(school),
+        }
+    }
+}
+
+/// A way to group applicants by their profile when reporting on a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stratification {
+    School,
+    Visa,
+    /// Exam scores in bands of 10 points.
+    ExamScore,
+    /// Ages in bands of 5 years.
+    Age,
 }
-*/
+
+impl Stratification {
+    /// The group a profile falls in.
+    pub fn key(&self, profile: &Profile) -> String {
+        match self {
+            Stratification::School => format!("{:?}", profile.school),
+            Stratification::Visa => match profile.visa {
+                true => "visa".to_string(),
+                false => "no visa".to_string()
+            },
+            Stratification::ExamScore => format!("{}-{}", profile.exam_score / 10 * 10, profile.exam_score / 10 * 10 + 9),
+            Stratification::Age => format!("{}-{}", profile.age / 5 * 5, profile.age / 5 * 5 + 4),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Applicant {
     pub id: u32,
-    // pub name: String,
-    // pub age: u8,
-    // pub email: String,
-    // pub phone: String,
-    // pub status: MatchStatus,
-    pub applications: u8,
+    pub status: MatchStatus,
+    pub applications: u16,
     pub competitiveness: f32,
+    pub specialty: Specialty,
     pub couple: Option<u32>,
     pub ranking: Vec<u32>,
+    /// Supplemental rank lists of preliminary programs, by the advanced program they go with.
+    pub supplemental: HashMap<u32, Vec<u32>>,
+    pub profile: Option<Profile>,
 }
 
 impl Applicant {
-/*    pub fn new(name: String, age: u8, email: String, phone: String, applications: u8, competitiveness: f32, couple: Option<u32>) -> Applicant {
-        Applicant {
-            id: APPLICANT_COUNTER.fetch_add(1, Ordering::SeqCst),
-            name,
-            age,
-            email,
-            phone,
-            applications,
-            competitiveness,
-            couple,
-            ranking: Vec::new(),
-        }
-    }
-*/
-    fn sample_applicant_(can_couple: bool) -> (Applicant, Option<Applicant>) {
+    pub fn sample_applicant_(couple_rate: f64) -> (Applicant, Option<Applicant>) {
         let id = APPLICANT_COUNTER.fetch_add(1, Ordering::SeqCst);
-        let mut competitiveness = generator::random_competitiveness();
-        let mut couple: Option<Applicant> = match can_couple && generator::is_coupled() {
-            true => Some(Applicant::sample_applicant_(false).0),
+        let competitiveness = generator::random_competitiveness();
+        let mut couple: Option<Applicant> = match couple_rate > 0.0 && generator::is_coupled(couple_rate) {
+            true => Some(Applicant::sample_applicant_(0.0).0),
             false => None
         };
         if let Some(a) = &mut couple {
             a.couple = Some(id);
-            competitiveness = (competitiveness + a.competitiveness) / 2.0;
-            a.competitiveness = competitiveness;
-            a.applications = (competitiveness * 100.0) as u8 + 1;
         }
         (
             Applicant {
                 id,
-                // name: generator::random_name(),
-                // age: generator::random_age(),
-                // email: generator::random_email(),
-                // phone: generator::random_phone(),
-                // status: MatchStatus::PENDING,
-                applications: ((1.0 - competitiveness) * 100.0) as u8 + 1,
+                status: MatchStatus::Registered,
+                applications: ((1.0 - competitiveness) * 100.0) as u16 + 1,
                 competitiveness,
+                specialty: generator::random_specialty(),
                 couple: couple.clone().map(|a| a.id),
                 ranking: Vec::new(),
+                supplemental: HashMap::new(),
+                profile: Some(Profile::sample_profile(competitiveness)),
             },
             couple
         )
     }
 
     pub fn sample_applicant() -> (Applicant, Option<Applicant>) {
-        Self::sample_applicant_(true)
+        Self::sample_applicant_(generator::COUPLE_RATE)
     }
 }
 
@@ -100,6 +226,28 @@ impl HasCouple for Applicant {
     }
 }
 
+impl HasSupplemental for Applicant {
+    fn supplemental(&self, advanced: u32) -> Option<Vec<u32>> {
+        self.supplemental.get(&advanced).cloned()
+    }
+
+    fn has_supplemental(&self) -> bool {
+        !self.supplemental.is_empty()
+    }
+}
+
+impl HasProfile for Applicant {
+    fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+}
+
+impl HasSpecialty for Applicant {
+    fn specialty(&self) -> Specialty {
+        self.specialty
+    }
+}
+
 impl Rankable<Program> for Applicant {
     fn id(&self) -> u32 {
         self.id
@@ -112,46 +260,81 @@ impl Rankable<Program> for Applicant {
     fn add_ranking(&mut self, to_add: &Program) {
         self.ranking.push(to_add.id)
     }
+
+    fn set_ranking(&mut self, ranking: Vec<u32>) {
+        self.ranking = ranking;
+    }
 }
 
 impl HasApplications for Applicant {
-    fn applications(&self) -> u8 {
+    fn applications(&self) -> u16 {
         self.applications
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Application {
+    pub applicant: u32,
+    pub competitiveness: f32,
+    pub couple: Option<u32>,
+    pub signal: bool,
+    pub interviewed: bool,
+}
+
+/// One of a program's tracks, with its own positions and rank list. Applicants rank tracks
+/// by their id, as they would programs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Track {
+    pub id: u32,
+    pub capacity: u16,
+    pub ranking: Vec<u32>,
+    /// The track of the same program this track's unfilled positions revert to, if any.
+    pub reverts_to: Option<u32>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
     pub id: u32,
-    // pub name: String,
-    // pub deadline: DateTime<Utc>,
-    pub capacity: u8,
+    pub name: String,
+    pub institution: String,
+    /// When the program's rank list has to be certified by to be used in the match.
+    pub deadline: DateTime<Utc>,
+    /// When the program certified its rank list, if it has.
+    pub certified: Option<DateTime<Utc>>,
+    pub capacity: u16,
     pub competitiveness: f32,
-    pub applications: Vec<(u32, f32)>,
+    pub specialty: Specialty,
+    pub position_type: PositionType,
+    pub applications: Vec<Application>,
     pub ranking: Vec<u32>,
+    /// Sizes of the tiers `ranking` is split into, best first. Applicants in the same tier
+    /// are tied, and are only in the order `ranking` has them in until the ties are broken.
+    /// Empty if the program ranked applicants in a strict order.
+    pub tiers: Vec<u32>,
+    /// A program with tracks is matched through them, and its own capacity and rank list
+    /// are not used.
+    pub tracks: Vec<Track>,
 }
 
 impl Program {
-/*    pub fn new(name: String, deadline: DateTime<Utc>, capacity: u8, competitiveness: f32) -> Program {
-        Program {
-            id: PROGRAM_COUNTER.fetch_add(1, Ordering::SeqCst),
-            name,
-            // deadline,
-            capacity,
-            competitiveness,
-            ranking: Vec::new(),
-        }
-    }
-*/
     pub fn sample_program() -> Program {
+        let institution = generator::random_institution();
+        let specialty = generator::random_specialty();
+        let deadline = generator::random_deadline();
         Program {
             id: PROGRAM_COUNTER.fetch_add(1, Ordering::SeqCst),
-            // name: generator::random_name(),
-            // deadline: generator::random_deadline(),
+            name: format!("{} {:?}", institution, specialty),
+            institution,
+            deadline,
+            certified: Some(generator::random_certification(deadline)),
             capacity: generator::random_capacity(),
             competitiveness: generator::random_competitiveness(),
+            specialty,
+            position_type: PositionType::Categorical,
             applications: Vec::new(),
             ranking: Vec::new(),
+            tiers: Vec::new(),
+            tracks: Vec::new(),
         }
     }
 }
@@ -174,29 +357,116 @@ impl Rankable<Applicant> for Program {
     fn add_ranking(&mut self, to_add: &Applicant) {
         self.ranking.push(to_add.id);
     }
+
+    fn set_ranking(&mut self, ranking: Vec<u32>) {
+        self.ranking = ranking;
+    }
 }
 
 impl HasCapacity for Program {
-    fn capacity(&self) -> u8 {
+    fn capacity(&self) -> u16 {
         self.capacity
     }
 }
 
+impl HasPositionType for Program {
+    fn position_type(&self) -> PositionType {
+        self.position_type
+    }
+}
+
+impl HasSpecialty for Program {
+    fn specialty(&self) -> Specialty {
+        self.specialty
+    }
+}
+
 impl ReceiveApplication<Applicant> for Program {
-    fn receive_application(&mut self, applicant: &Applicant) {
-        self.applications.push((applicant.id, applicant.competitiveness));
-        self.applications.sort_by(|(_, comp_a), (_, comp_b)| comp_b.total_cmp(comp_a));
-    }
-    
-    fn process_applications(&mut self) {
-        self.applications.sort_by(|(_, comp_a), (_, comp_b)| comp_b.total_cmp(comp_a));
-        for (a, _) in self.applications.iter().take((15 * self.capacity) as usize) {
-            self.ranking.push(*a);
+    fn receive_application(&mut self, applicant: &Applicant, signal: bool) {
+        self.applications.push(Application {
+            applicant: applicant.id,
+            competitiveness: applicant.competitiveness,
+            couple: applicant.couple,
+            signal,
+            interviewed: false,
+        });
+    }
+
+    fn process_applications(&mut self, policy: &ProgramRankPolicy) {
+        let mut rng = generator::seeded_rng(policy.seed ^ self.id as u64);
+        let mut scores: Vec<(usize, f32)> = self.applications.iter().enumerate().map(|(i, a)| {
+            let mut score = a.competitiveness + generator::random_fit(&mut rng, policy.fit_noise);
+            if a.signal {
+                score += policy.signal_bonus;
+            }
+            if a.competitiveness > self.competitiveness + policy.safety_margin {
+                score -= policy.safety_penalty;
+            }
+            (i, score)
+        }).collect();
+
+        if let Some(interviews) = policy.interviews {
+            // interview the strongest applications, then rank them with the interview in mind
+            let slots = interviews as usize * self.capacity as usize;
+            select_best(&mut scores, slots, |(i, a), (j, b)| b.total_cmp(a).then(i.cmp(j)));
+            for (i, score) in scores.iter_mut().take(slots) {
+                self.applications[*i].interviewed = true;
+                *score += policy.interview_bonus;
+            }
+        }
+
+        // partners of a couple are ranked together by pairing them under the same key
+        let key = |a: &Application| match a.couple {
+            Some(c) if policy.joint_couples => u32::min(a.applicant, c),
+            _ => a.applicant
+        };
+        if policy.joint_couples {
+            let mut joint: HashMap<u32, f32> = HashMap::new();
+            for (i, score) in scores.iter() {
+                let best = joint.entry(key(&self.applications[*i])).or_insert(*score);
+                *best = f32::max(*best, *score);
+            }
+            for (i, score) in scores.iter_mut() {
+                *score = joint[&key(&self.applications[*i])];
+            }
+        }
+
+        // only the applicants that make the rank list need to be sorted
+        let depth = usize::min(policy.depth as usize * self.capacity as usize, scores.len());
+        let rank_order = |(i, a): &(usize, f32), (j, b): &(usize, f32)| b.total_cmp(a)
+            .then(key(&self.applications[*i]).cmp(&key(&self.applications[*j])))
+            .then(i.cmp(j));
+        select_best(&mut scores, depth, rank_order);
+        scores[..depth].sort_unstable_by(rank_order);
+        for (i, _) in scores.iter().take(depth) {
+            self.ranking.push(self.applications[*i].applicant);
+        }
+
+        if policy.tier_width > 0.0 {
+            let band = |score: f32| (score / policy.tier_width).floor();
+            let mut tiers: Vec<(f32, u32)> = Vec::new();
+            for (_, score) in scores.iter().take(depth) {
+                match tiers.last_mut() {
+                    Some((b, size)) if *b == band(*score) => *size += 1,
+                    _ => tiers.push((band(*score), 1))
+                }
+            }
+            self.tiers = tiers.into_iter().map(|(_, size)| size).collect();
         }
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Moves the `n` best (smallest by `compare`) scores to the front, in no particular order,
+/// without sorting the rest.
+fn select_best<F>(scores: &mut [(usize, f32)], n: usize, compare: F)
+where F: FnMut(&(usize, f32), &(usize, f32)) -> std::cmp::Ordering
+{
+    if n > 0 && n < scores.len() {
+        scores.select_nth_unstable_by(n - 1, compare);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Couple<A>(pub A, pub Option<A>);
 
 impl<A> From<(A, A)> for Couple<A> {
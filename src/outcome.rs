@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+use serde::{Deserialize, Serialize};
+use crate::parameters::MatchParameters;
+use crate::stability::Assignment;
+
+/// Outcome files start with this, followed by the format version.
+const MAGIC: [u8; 4] = *b"RMMO";
+const VERSION: u32 = 1;
+
+/// What a match run came to, with what is needed to judge it: every applicant's rank list
+/// and every program's positions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchOutcome {
+    /// The program each matched applicant was placed with, by applicant id.
+    pub assigned: BTreeMap<u32, u32>,
+    /// Every applicant's rank list, by applicant id. For a partner in a couple, their half
+    /// of the joint rank list.
+    pub rankings: BTreeMap<u32, Vec<u32>>,
+    /// Every program's positions, by program id.
+    pub capacities: BTreeMap<u32, u16>,
+}
+
+impl MatchOutcome {
+    pub fn new(parameters: &MatchParameters, assigned: &Assignment) -> MatchOutcome {
+        MatchOutcome {
+            assigned: assigned.iter().map(|(a, p)| (*a, *p)).collect(),
+            rankings: parameters.applicants.iter()
+                .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+                .map(|a| (a.id, a.ranking.clone()))
+                .collect(),
+            capacities: parameters.programs.iter().map(|p| (p.id, p.capacity)).collect(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> bincode::Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writer.write_all(&MAGIC)?;
+        bincode::serialize_into(&mut writer, &VERSION)?;
+        bincode::serialize_into(writer, self)
+    }
+
+    pub fn open(path: &str) -> bincode::Result<Self> {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!("{} is not an outcome file", path))));
+        }
+        match bincode::deserialize_from::<_, u32>(&mut reader)? {
+            VERSION => bincode::deserialize_from(reader),
+            version => Err(Box::new(bincode::ErrorKind::Custom(format!("unsupported outcome file version {}", version))))
+        }
+    }
+
+    /// Where the applicant matched on their rank list, counting from 0, or its length if they
+    /// didn't match (or matched somewhere they didn't rank).
+    fn choice(&self, applicant: u32, program: Option<u32>) -> usize {
+        let ranking = self.rankings.get(&applicant).map_or(&[][..], |r| r.as_slice());
+        program.and_then(|p| ranking.iter().position(|q| *q == p)).unwrap_or(ranking.len())
+    }
+
+    fn filled(&self) -> BTreeMap<u32, usize> {
+        let mut filled: BTreeMap<u32, usize> = BTreeMap::new();
+        for p in self.assigned.values() {
+            *filled.entry(*p).or_insert(0) += 1;
+        }
+        filled
+    }
+}
+
+/// Whether an applicant did better or worse the second time, by their own rank list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Better,
+    Worse,
+    /// Matched somewhere else no higher or lower on their rank list, which can only happen
+    /// if their rank list changed.
+    Same,
+}
+
+/// An applicant who matched differently the second time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssignmentChange {
+    pub applicant: u32,
+    pub before: Option<u32>,
+    pub after: Option<u32>,
+    pub direction: Direction,
+    /// How many places up their rank list they moved, with not matching counting as one
+    /// past the end of it. Negative if they moved down.
+    pub places: i64,
+}
+
+/// A program that filled a different number of positions the second time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FillChange {
+    pub program: u32,
+    pub capacity_before: u16,
+    pub capacity_after: u16,
+    pub filled_before: usize,
+    pub filled_after: usize,
+}
+
+/// How a second match outcome differs from a first, for the applicants and programs in both.
+/// Applicants are judged by their rank lists in the first outcome.
+#[derive(Debug, Clone, Default)]
+pub struct OutcomeDiff {
+    pub changes: Vec<AssignmentChange>,
+    pub fills: Vec<FillChange>,
+    /// Applicants in only one of the outcomes.
+    pub only_before: Vec<u32>,
+    pub only_after: Vec<u32>,
+    pub matched_before: usize,
+    pub matched_after: usize,
+}
+
+impl OutcomeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.fills.is_empty() && self.only_before.is_empty() && self.only_after.is_empty()
+    }
+
+    pub fn count(&self, direction: Direction) -> usize {
+        self.changes.iter().filter(|c| c.direction == direction).count()
+    }
+
+    /// Applicants who matched the second time but not the first.
+    pub fn newly_matched(&self) -> usize {
+        self.changes.iter().filter(|c| c.before.is_none()).count()
+    }
+
+    /// Applicants who matched the first time but not the second.
+    pub fn newly_unmatched(&self) -> usize {
+        self.changes.iter().filter(|c| c.after.is_none()).count()
+    }
+
+    /// Average places moved up their rank lists by applicants who matched differently.
+    pub fn mean_places(&self) -> f32 {
+        self.changes.iter().map(|c| c.places).sum::<i64>() as f32 / self.changes.len().max(1) as f32
+    }
+}
+
+/// Lists every applicant who matched differently in `after` than in `before`, and every
+/// program that filled a different number of positions.
+pub fn diff(before: &MatchOutcome, after: &MatchOutcome) -> OutcomeDiff {
+    let applicants_before: BTreeSet<u32> = before.rankings.keys().copied().collect();
+    let applicants_after: BTreeSet<u32> = after.rankings.keys().copied().collect();
+
+    let changes = applicants_before.intersection(&applicants_after)
+        .filter_map(|a| {
+            let (p, q) = (before.assigned.get(a).copied(), after.assigned.get(a).copied());
+            if p == q {
+                return None;
+            }
+            let places = before.choice(*a, p) as i64 - before.choice(*a, q) as i64;
+            let direction = match places {
+                0 => Direction::Same,
+                n if n > 0 => Direction::Better,
+                _ => Direction::Worse
+            };
+            Some(AssignmentChange { applicant: *a, before: p, after: q, direction, places })
+        })
+        .collect();
+
+    let (filled_before, filled_after) = (before.filled(), after.filled());
+    let fills = before.capacities.iter()
+        .filter_map(|(p, capacity)| after.capacities.get(p).map(|c| (*p, *capacity, *c)))
+        .map(|(program, capacity_before, capacity_after)| FillChange {
+            program,
+            capacity_before,
+            capacity_after,
+            filled_before: filled_before.get(&program).copied().unwrap_or(0),
+            filled_after: filled_after.get(&program).copied().unwrap_or(0),
+        })
+        .filter(|f| f.filled_before != f.filled_after)
+        .collect();
+
+    OutcomeDiff {
+        changes,
+        fills,
+        only_before: applicants_before.difference(&applicants_after).copied().collect(),
+        only_after: applicants_after.difference(&applicants_before).copied().collect(),
+        matched_before: before.assigned.len(),
+        matched_after: after.assigned.len(),
+    }
+}
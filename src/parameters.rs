@@ -1,7 +1,33 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use crate::models::{Applicant, Couple, Program};
+use crate::models::{Applicant, Application, Couple, MatchStatus, PositionType, Profile, Program, Specialty, Track};
 
-#[derive(Clone, Serialize, Deserialize)]
+/// Data files start with this, followed by the format version.
+const MAGIC: [u8; 4] = *b"RMDF";
+/// Version 2 widened capacities and application counts to `u16`, version 3 added applicant
+/// profiles, version 4 program names, institutions and deadlines, version 5 applicant match
+/// statuses and version 6 ties in program rank lists. Files written before versions were
+/// recorded have no header, and are read as version 1.
+const VERSION: u32 = 6;
+
+/// A small change to a match, which `Matcher::rematch` can catch up with without starting over.
+#[derive(Debug, Clone)]
+pub enum Delta {
+    /// A program now has a different number of positions.
+    Capacity { program: u32, capacity: u16 },
+    /// An applicant withdrew. If they were in a couple, their partner stays in the match
+    /// on their own.
+    Withdrawal { applicant: u32 },
+    /// An applicant submitted a new rank list. For a partner in a couple this is their half
+    /// of the joint rank list, so it has to stay as long as their partner's.
+    ApplicantRanking { applicant: u32, ranking: Vec<u32> },
+    /// A program submitted a new rank list.
+    ProgramRanking { program: u32, ranking: Vec<u32> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchParameters {
     pub applicants: Vec<Couple<Applicant>>,
     pub programs: Vec<Program>,
@@ -12,13 +38,421 @@ pub struct MatchParameters {
 impl MatchParameters {
     pub fn save(&self, path: &str) -> bincode::Result<()> {
         let file = std::fs::File::create(path)?;
-        let writer = std::io::BufWriter::new(file);
+        let mut writer = std::io::BufWriter::new(file);
+        writer.write_all(&MAGIC)?;
+        bincode::serialize_into(&mut writer, &VERSION)?;
         bincode::serialize_into(writer, self)
     }
-    
+
+    /// Reads a data file written by `save`, migrating files written by earlier versions.
     pub fn open(path: &str) -> bincode::Result<Self> {
         let file = std::fs::File::open(path)?;
-        let reader = std::io::BufReader::new(file);
-        bincode::deserialize_from(reader)
+        let mut reader = std::io::BufReader::new(file);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            // no header, so the four bytes read were the start of a version 1 file
+            let reader = std::io::Cursor::new(magic).chain(reader);
+            return bincode::deserialize_from::<_, v1::MatchParameters>(reader).map(MatchParameters::from);
+        }
+        match bincode::deserialize_from::<_, u32>(&mut reader)? {
+            2 => bincode::deserialize_from::<_, v2::MatchParameters>(reader).map(MatchParameters::from),
+            3 => bincode::deserialize_from::<_, v3::MatchParameters>(reader).map(MatchParameters::from),
+            4 => bincode::deserialize_from::<_, v4::MatchParameters>(reader).map(MatchParameters::from),
+            5 => bincode::deserialize_from::<_, v5::MatchParameters>(reader).map(MatchParameters::from),
+            VERSION => bincode::deserialize_from(reader),
+            version => Err(Box::new(bincode::ErrorKind::Custom(format!("unsupported data file version {}", version))))
+        }
+    }
+
+    pub fn apply(&mut self, delta: &Delta) {
+        match delta {
+            Delta::Capacity { program, capacity } => {
+                if let Some(p) = self.programs.iter_mut().find(|p| p.id == *program) {
+                    p.capacity = *capacity;
+                }
+            },
+            Delta::Withdrawal { applicant } => {
+                let i = match self.applicants.iter().position(|c| c.0.id == *applicant || c.1.as_ref().is_some_and(|b| b.id == *applicant)) {
+                    None => return,
+                    Some(i) => i
+                };
+                let c = &mut self.applicants[i];
+                let partner = match c.1.take() {
+                    None => None,
+                    Some(b) if b.id == *applicant => Some(c.0.clone()),
+                    Some(b) => Some(b)
+                };
+                match partner {
+                    None => {
+                        self.applicants.remove(i);
+                    },
+                    Some(mut partner) => {
+                        // their half of the joint rank list can name a program more than once
+                        let mut ranked = HashSet::new();
+                        partner.ranking.retain(|p| ranked.insert(*p));
+                        partner.couple = None;
+                        self.applicants[i] = Couple(partner, None);
+                    }
+                }
+                self.num_applicants -= 1;
+            },
+            Delta::ApplicantRanking { applicant, ranking } => {
+                let a = self.applicants.iter_mut()
+                    .flat_map(|c| std::iter::once(&mut c.0).chain(c.1.as_mut()))
+                    .find(|a| a.id == *applicant);
+                if let Some(a) = a {
+                    a.ranking = ranking.clone();
+                }
+            },
+            Delta::ProgramRanking { program, ranking } => {
+                if let Some(p) = self.programs.iter_mut().find(|p| p.id == *program) {
+                    p.ranking = ranking.clone();
+                }
+            }
+        }
+    }
+}
+
+/// The data file layout before versions were recorded, with capacities and application
+/// counts as `u8` and no specialties, position types, supplemental rank lists or tracks.
+mod v1 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub struct Applicant {
+        pub id: u32,
+        pub applications: u8,
+        pub competitiveness: f32,
+        pub couple: Option<u32>,
+        pub ranking: Vec<u32>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Program {
+        pub id: u32,
+        pub capacity: u8,
+        pub competitiveness: f32,
+        /// Each application's applicant and their competitiveness.
+        pub applications: Vec<(u32, f32)>,
+        pub ranking: Vec<u32>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct MatchParameters {
+        pub applicants: Vec<Couple<Applicant>>,
+        pub programs: Vec<Program>,
+        pub num_programs: usize,
+        pub num_applicants: usize,
     }
-}
\ No newline at end of file
+}
+
+/// The data file layout before applicants had profiles.
+mod v2 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub struct Applicant {
+        pub id: u32,
+        pub applications: u16,
+        pub competitiveness: f32,
+        pub specialty: Specialty,
+        pub couple: Option<u32>,
+        pub ranking: Vec<u32>,
+        pub supplemental: HashMap<u32, Vec<u32>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct MatchParameters {
+        pub applicants: Vec<Couple<Applicant>>,
+        pub programs: Vec<v3::Program>,
+        pub num_programs: usize,
+        pub num_applicants: usize,
+    }
+}
+
+/// The data file layout before programs had names, institutions and deadlines.
+mod v3 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub struct Program {
+        pub id: u32,
+        pub capacity: u16,
+        pub competitiveness: f32,
+        pub specialty: Specialty,
+        pub position_type: PositionType,
+        pub applications: Vec<Application>,
+        pub ranking: Vec<u32>,
+        pub tracks: Vec<Track>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct MatchParameters {
+        pub applicants: Vec<Couple<v4::Applicant>>,
+        pub programs: Vec<Program>,
+        pub num_programs: usize,
+        pub num_applicants: usize,
+    }
+}
+
+/// The data file layout before applicants had match statuses.
+mod v4 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub struct Applicant {
+        pub id: u32,
+        pub applications: u16,
+        pub competitiveness: f32,
+        pub specialty: Specialty,
+        pub couple: Option<u32>,
+        pub ranking: Vec<u32>,
+        pub supplemental: HashMap<u32, Vec<u32>>,
+        pub profile: Option<Profile>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct MatchParameters {
+        pub applicants: Vec<Couple<Applicant>>,
+        pub programs: Vec<v5::Program>,
+        pub num_programs: usize,
+        pub num_applicants: usize,
+    }
+}
+
+/// The data file layout before program rank lists could have ties.
+mod v5 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub struct Program {
+        pub id: u32,
+        pub name: String,
+        pub institution: String,
+        pub deadline: DateTime<Utc>,
+        pub certified: Option<DateTime<Utc>>,
+        pub capacity: u16,
+        pub competitiveness: f32,
+        pub specialty: Specialty,
+        pub position_type: PositionType,
+        pub applications: Vec<Application>,
+        pub ranking: Vec<u32>,
+        pub tracks: Vec<Track>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct MatchParameters {
+        pub applicants: Vec<Couple<crate::models::Applicant>>,
+        pub programs: Vec<Program>,
+        pub num_programs: usize,
+        pub num_applicants: usize,
+    }
+}
+
+/// Everyone in a version 1 file is in the one specialty, since specialties weren't recorded.
+const V1_SPECIALTY: Specialty = Specialty::InternalMedicine;
+
+impl From<v1::Applicant> for v2::Applicant {
+    fn from(a: v1::Applicant) -> v2::Applicant {
+        v2::Applicant {
+            id: a.id,
+            applications: a.applications.into(),
+            competitiveness: a.competitiveness,
+            specialty: V1_SPECIALTY,
+            couple: a.couple,
+            ranking: a.ranking,
+            supplemental: HashMap::new(),
+        }
+    }
+}
+
+/// Programs from before position types and tracks were recorded are categorical, with no
+/// tracks. Their applications are neither signalled nor interviewed, and record the
+/// applicant's partner from `partners`.
+fn migrate_v1_program(p: v1::Program, partners: &HashMap<u32, u32>) -> v3::Program {
+    v3::Program {
+        id: p.id,
+        capacity: p.capacity.into(),
+        competitiveness: p.competitiveness,
+        specialty: V1_SPECIALTY,
+        position_type: PositionType::Categorical,
+        applications: p.applications.into_iter()
+            .map(|(applicant, competitiveness)| Application {
+                applicant,
+                competitiveness,
+                couple: partners.get(&applicant).copied(),
+                signal: false,
+                interviewed: false,
+            })
+            .collect(),
+        ranking: p.ranking,
+        tracks: Vec::new(),
+    }
+}
+
+impl From<v1::MatchParameters> for v2::MatchParameters {
+    fn from(parameters: v1::MatchParameters) -> v2::MatchParameters {
+        let partners: HashMap<u32, u32> = parameters.applicants.iter()
+            .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+            .filter_map(|a| a.couple.map(|b| (a.id, b)))
+            .collect();
+        v2::MatchParameters {
+            applicants: parameters.applicants.into_iter()
+                .map(|c| Couple(c.0.into(), c.1.map(v2::Applicant::from)))
+                .collect(),
+            programs: parameters.programs.into_iter().map(|p| migrate_v1_program(p, &partners)).collect(),
+            num_programs: parameters.num_programs,
+            num_applicants: parameters.num_applicants,
+        }
+    }
+}
+
+impl From<v2::Applicant> for v4::Applicant {
+    fn from(a: v2::Applicant) -> v4::Applicant {
+        v4::Applicant {
+            id: a.id,
+            applications: a.applications,
+            competitiveness: a.competitiveness,
+            specialty: a.specialty,
+            couple: a.couple,
+            ranking: a.ranking,
+            supplemental: a.supplemental,
+            profile: None,
+        }
+    }
+}
+
+impl From<v2::MatchParameters> for v3::MatchParameters {
+    fn from(parameters: v2::MatchParameters) -> v3::MatchParameters {
+        v3::MatchParameters {
+            applicants: parameters.applicants.into_iter()
+                .map(|c| Couple(c.0.into(), c.1.map(v4::Applicant::from)))
+                .collect(),
+            programs: parameters.programs,
+            num_programs: parameters.num_programs,
+            num_applicants: parameters.num_applicants,
+        }
+    }
+}
+
+impl From<v3::Program> for v5::Program {
+    /// Programs from before deadlines were recorded count as certified on time.
+    fn from(p: v3::Program) -> v5::Program {
+        v5::Program {
+            id: p.id,
+            name: String::new(),
+            institution: String::new(),
+            deadline: DateTime::default(),
+            certified: Some(DateTime::default()),
+            capacity: p.capacity,
+            competitiveness: p.competitiveness,
+            specialty: p.specialty,
+            position_type: p.position_type,
+            applications: p.applications,
+            ranking: p.ranking,
+            tracks: p.tracks,
+        }
+    }
+}
+
+impl From<v3::MatchParameters> for v4::MatchParameters {
+    fn from(parameters: v3::MatchParameters) -> v4::MatchParameters {
+        v4::MatchParameters {
+            applicants: parameters.applicants,
+            programs: parameters.programs.into_iter().map(v5::Program::from).collect(),
+            num_programs: parameters.num_programs,
+            num_applicants: parameters.num_applicants,
+        }
+    }
+}
+
+impl From<v4::Applicant> for Applicant {
+    /// Applicants from before statuses were recorded count as having gone into the match if
+    /// they have a rank list.
+    fn from(a: v4::Applicant) -> Applicant {
+        Applicant {
+            id: a.id,
+            status: match a.ranking.is_empty() {
+                true => MatchStatus::Registered,
+                false => MatchStatus::Certified
+            },
+            applications: a.applications,
+            competitiveness: a.competitiveness,
+            specialty: a.specialty,
+            couple: a.couple,
+            ranking: a.ranking,
+            supplemental: a.supplemental,
+            profile: a.profile,
+        }
+    }
+}
+
+impl From<v4::MatchParameters> for v5::MatchParameters {
+    fn from(parameters: v4::MatchParameters) -> v5::MatchParameters {
+        v5::MatchParameters {
+            applicants: parameters.applicants.into_iter()
+                .map(|c| Couple(c.0.into(), c.1.map(Applicant::from)))
+                .collect(),
+            programs: parameters.programs,
+            num_programs: parameters.num_programs,
+            num_applicants: parameters.num_applicants,
+        }
+    }
+}
+
+impl From<v5::Program> for Program {
+    fn from(p: v5::Program) -> Program {
+        Program {
+            id: p.id,
+            name: p.name,
+            institution: p.institution,
+            deadline: p.deadline,
+            certified: p.certified,
+            capacity: p.capacity,
+            competitiveness: p.competitiveness,
+            specialty: p.specialty,
+            position_type: p.position_type,
+            applications: p.applications,
+            ranking: p.ranking,
+            tiers: Vec::new(),
+            tracks: p.tracks,
+        }
+    }
+}
+
+impl From<v5::MatchParameters> for MatchParameters {
+    fn from(parameters: v5::MatchParameters) -> MatchParameters {
+        MatchParameters {
+            applicants: parameters.applicants,
+            programs: parameters.programs.into_iter().map(Program::from).collect(),
+            num_programs: parameters.num_programs,
+            num_applicants: parameters.num_applicants,
+        }
+    }
+}
+
+// older layouts migrate one version at a time
+impl From<v4::MatchParameters> for MatchParameters {
+    fn from(parameters: v4::MatchParameters) -> MatchParameters {
+        v5::MatchParameters::from(parameters).into()
+    }
+}
+
+impl From<v3::MatchParameters> for MatchParameters {
+    fn from(parameters: v3::MatchParameters) -> MatchParameters {
+        v4::MatchParameters::from(parameters).into()
+    }
+}
+
+impl From<v2::MatchParameters> for MatchParameters {
+    fn from(parameters: v2::MatchParameters) -> MatchParameters {
+        v3::MatchParameters::from(parameters).into()
+    }
+}
+
+impl From<v1::MatchParameters> for MatchParameters {
+    fn from(parameters: v1::MatchParameters) -> MatchParameters {
+        v2::MatchParameters::from(parameters).into()
+    }
+}
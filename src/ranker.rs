@@ -1,4 +1,5 @@
-use crate::models::{Couple, HasApplications};
+use std::collections::HashMap;
+use crate::models::{Couple, HasApplications, HasCapacity, HasSpecialty};
 
 pub trait Competitive {
     fn competitiveness(&self) -> f32;
@@ -7,20 +8,128 @@ pub trait Competitive {
 pub trait ReceiveApplication<A>
 where A: Competitive
 {
-    fn receive_application(&mut self, applicant: &A);
-    fn process_applications(&mut self);
+    fn receive_application(&mut self, applicant: &A, signal: bool);
+    fn process_applications(&mut self, policy: &ProgramRankPolicy);
 }
 
+/// How a program turns the applications it received into a rank list.
+///
+/// Every application starts with the applicant's competitiveness as its score, which the
+/// policy then adjusts before the program ranks the highest scores.
+#[derive(Debug, Clone)]
+pub struct ProgramRankPolicy {
+    /// Number of applicants ranked per position.
+    pub depth: u8,
+    /// Largest "fit" adjustment (up or down) applied to an applicant's score.
+    pub fit_noise: f32,
+    /// Penalty for applicants that look like they are using the program as a safety,
+    /// i.e. applicants more competitive than the program by over `safety_margin`.
+    pub safety_penalty: f32,
+    pub safety_margin: f32,
+    /// Bonus for applicants that signalled the program.
+    pub signal_bonus: f32,
+    /// Number of applicants interviewed per position, if the program interviews.
+    pub interviews: Option<u8>,
+    /// Bonus for applicants the program interviewed.
+    pub interview_bonus: f32,
+    /// Rank both partners of a couple next to each other, at the stronger partner's score.
+    pub joint_couples: bool,
+    /// Width of the score bands the rank list is split into tiers by, so applicants whose
+    /// scores fall in the same band are tied. Zero ranks in a strict order.
+    pub tier_width: f32,
+    /// Seed for the fit noise, so the same policy always produces the same rank lists.
+    pub seed: u64,
+}
+
+impl Default for ProgramRankPolicy {
+    /// Rank the top 15 applicants per position by competitiveness.
+    fn default() -> Self {
+        ProgramRankPolicy {
+            depth: 15,
+            fit_noise: 0.0,
+            safety_penalty: 0.0,
+            safety_margin: 0.0,
+            signal_bonus: 0.0,
+            interviews: None,
+            interview_bonus: 0.0,
+            joint_couples: false,
+            tier_width: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RankError {
+    InvalidStrategy(String),
+    InvalidDistribution(String),
+}
+
+impl std::fmt::Display for RankError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RankError::InvalidStrategy(s) => write!(f, "Invalid rank strategy: {}", s),
+            RankError::InvalidDistribution(s) => write!(f, "Invalid rank distribution: {}", s),
+        }
+    }
+}
+
+/// Bounds of the reach, realistic and safety bands, as multiples of an applicant's competitiveness.
+///
+/// Reach programs are below `reach_multiplier` and at or above `realistic_multiplier`,
+/// realistic programs are below `realistic_multiplier` and at or above `safety_multiplier`,
+/// and safety programs are anything below `safety_multiplier`.
+#[derive(Debug, Clone)]
 pub struct RankStrategy {
-    pub reach_multiplier: f32,
-    pub realistic_multiplier: f32,
-    pub safety_multiplier: f32
+    reach_multiplier: f32,
+    realistic_multiplier: f32,
+    safety_multiplier: f32
+}
+
+impl RankStrategy {
+    pub fn new(reach_multiplier: f32, realistic_multiplier: f32, safety_multiplier: f32) -> Result<RankStrategy, RankError> {
+        let multipliers = [reach_multiplier, realistic_multiplier, safety_multiplier];
+        if multipliers.iter().any(|m| !m.is_finite() || *m <= 0.0) {
+            return Err(RankError::InvalidStrategy(format!("multipliers {:?} must be finite and positive", multipliers)));
+        }
+        if !(reach_multiplier > realistic_multiplier && realistic_multiplier > safety_multiplier) {
+            return Err(RankError::InvalidStrategy(format!("multipliers {:?} must be in descending order (reach > realistic > safety)", multipliers)));
+        }
+        Ok(RankStrategy { reach_multiplier, realistic_multiplier, safety_multiplier })
+    }
 }
 
+/// Fraction of an applicant's applications that go to each band.
+#[derive(Debug, Clone)]
 pub struct RankDistribution {
-    pub reach: f32,
-    pub realistic: f32,
-    pub safety: f32
+    reach: f32,
+    realistic: f32,
+    safety: f32
+}
+
+impl RankDistribution {
+    pub fn new(reach: f32, realistic: f32, safety: f32) -> Result<RankDistribution, RankError> {
+        let fractions = [reach, realistic, safety];
+        if fractions.iter().any(|f| !f.is_finite() || *f < 0.0) {
+            return Err(RankError::InvalidDistribution(format!("fractions {:?} must be finite and non-negative", fractions)));
+        }
+        let total: f32 = fractions.iter().sum();
+        if (total - 1.0).abs() > 1e-3 {
+            return Err(RankError::InvalidDistribution(format!("fractions {:?} sum to {}, not 1", fractions, total)));
+        }
+        // fractions a little over 1 would split off more applications than there are
+        Ok(RankDistribution { reach: reach / total, realistic: realistic / total, safety: safety / total })
+    }
+
+    /// Splits `applications` between the reach, realistic and safety bands.
+    /// Anything lost to rounding goes to the realistic band.
+    fn split(&self, applications: usize) -> [usize; 3] {
+        let reach = (applications as f32 * self.reach) as usize;
+        let realistic = (applications as f32 * self.realistic) as usize;
+        let safety = (applications as f32 * self.safety) as usize;
+        let leftover = applications.saturating_sub(reach + realistic + safety);
+        [reach, realistic + leftover, safety]
+    }
 }
 
 pub trait Rankable<T: Competitive>
@@ -29,61 +138,315 @@ where Self: Competitive
     fn id(&self) -> u32;
     fn ranking(&self) -> Vec<u32>;
     fn add_ranking(&mut self, to_add: &T);
+    fn set_ranking(&mut self, ranking: Vec<u32>);
+
+    /// Ranks the `num` candidates closest in competitiveness, returning how many
+    /// rankings could not be made because there were too few candidates.
+    fn naive_rank(&mut self, to_rank: &mut Vec<&T>, num: u32) -> usize {
+        self.sort_by_closeness(to_rank);
+
+        for program in to_rank.iter().take(num as usize) {
+            self.add_ranking(program);
+        }
+        (num as usize).saturating_sub(to_rank.len())
+    }
 
-    fn naive_rank(&mut self, to_rank: &mut Vec<&T>, num: u8) {
+    fn sort_by_closeness(&self, to_rank: &mut [&T]) {
         to_rank.sort_by(|a, b| {
             (a.competitiveness() - self.competitiveness())
                 .abs()
                 .partial_cmp(&(b.competitiveness() - self.competitiveness()).abs())
                 .unwrap()
         });
+    }
+}
 
-        for program in to_rank.iter().take(num.into()) {
-            self.add_ranking(program);
+/// The programs an applicant (or couple) applies to and the rank lists they submit.
+///
+/// Plans are worked out from a read-only view of the programs, so every applicant can be
+/// planned in parallel before the applications are handed to the programs in order.
+pub struct ApplicationPlan {
+    pub applications: Couple<Vec<u32>>,
+    pub rankings: Couple<Vec<u32>>,
+    /// Number of applications that could not be made.
+    pub shortfall: usize,
+}
+
+impl ApplicationPlan {
+    fn single(applications: Vec<u32>, shortfall: usize) -> ApplicationPlan {
+        ApplicationPlan {
+            rankings: Couple(applications.clone(), None),
+            applications: Couple(applications, None),
+            shortfall,
+        }
+    }
+
+    /// Each partner applies to their own programs, and the couple ranks compatible pairs,
+    /// as many as the partners made applications between them (every pair would be thousands
+    /// at realistic application counts).
+    fn couple<A, P>(first: Vec<u32>, second: Vec<u32>, shortfall: usize, programs: &[P]) -> ApplicationPlan
+    where A: Competitive,
+          P: Rankable<A> + HasCapacity
+    {
+        let pairs = joint_ranking(&first, &second, |p, q| compatible(programs, p, q), first.len() + second.len());
+        let rankings: (Vec<u32>, Vec<u32>) = pairs.into_iter().unzip();
+        ApplicationPlan {
+            rankings: rankings.into(),
+            applications: (first, second).into(),
+            shortfall,
+        }
+    }
+}
+
+/// Maps program IDs to their index in `programs`.
+pub fn program_index<A, P>(programs: &[P]) -> HashMap<u32, usize>
+where A: Competitive,
+      P: Rankable<A>
+{
+    programs.iter().enumerate().map(|(i, p)| (p.id(), i)).collect()
+}
+
+/// Applies to programs in the applicant's reach, realistic and safety bands, returning
+/// how many of the applicant's applications could not be made.
+///
+/// This is `plan` followed by `submit`; see `plan` for how the programs are chosen.
+pub fn rank<A, P>(applicant: &mut Couple<A>, programs: &mut [P],
+                  strategy: &RankStrategy, distribution: &RankDistribution,
+                  signals: u8, fallback: bool) -> usize
+where A: Rankable<P> + HasApplications + HasSpecialty + Competitive,
+      P: Rankable<A> + ReceiveApplication<A> + HasCapacity + HasSpecialty + Competitive
+{
+    let index = program_index::<A, P>(programs);
+    let plan = plan(applicant, programs, strategy, distribution, fallback);
+    submit(applicant, plan, programs, &index, signals)
+}
+
+/// Picks the programs in the applicant's reach, realistic and safety bands.
+///
+/// When a band has too few programs and `fallback` is set, the missing applications go to
+/// the other bands instead, trying the realistic band first, then safety, then reach.
+///
+/// Each partner of a couple applies to programs of their own specialty according to their
+/// own competitiveness, and the couple ranks pairs of those programs (see `joint_ranking`).
+pub fn plan<A, P>(applicant: &Couple<A>, programs: &[P],
+                  strategy: &RankStrategy, distribution: &RankDistribution,
+                  fallback: bool) -> ApplicationPlan
+where A: HasApplications + HasSpecialty + Competitive,
+      P: Rankable<A> + HasCapacity + HasSpecialty + Competitive
+{
+    let (first, shortfall) = choose_programs(&applicant.0, programs, strategy, distribution, fallback);
+    match &applicant.1 {
+        None => ApplicationPlan::single(first, shortfall),
+        Some(b) => {
+            let (second, short) = choose_programs(b, programs, strategy, distribution, fallback);
+            ApplicationPlan::couple::<A, P>(first, second, shortfall + short, programs)
         }
     }
 }
 
-pub fn rank<A, P>(applicant: &mut Couple<A>, programs: &mut Vec<P>,
-                  strategy: &RankStrategy, distribution: &RankDistribution)
-where A: Rankable<P> + HasApplications + Competitive,
+/// Sends the planned applications to the programs, signalling each applicant's first
+/// `signals` applications, and sets the applicants' rank lists. Returns the plan's shortfall.
+pub fn submit<A, P>(applicant: &mut Couple<A>, plan: ApplicationPlan, programs: &mut [P],
+                    index: &HashMap<u32, usize>, signals: u8) -> usize
+where A: Rankable<P> + Competitive,
       P: Rankable<A> + ReceiveApplication<A> + Competitive
 {
-    let a = &mut applicant.0;
-    let reach: Vec<u32> = programs.iter()
-        .filter(|p|
-                p.competitiveness() < strategy.reach_multiplier * a.competitiveness() &&
-                    p.competitiveness() >= f32::min(0.99, strategy.realistic_multiplier * a.competitiveness())
-        )
-        .take((a.applications() as f32 * distribution.reach) as usize)
-        .map(|p| p.id())
-        .collect();
-    let realistic: Vec<u32> = programs.iter()
-        .filter(|p|
-                p.competitiveness() < strategy.realistic_multiplier * a.competitiveness() &&
-                    p.competitiveness() >= f32::min(0.95, strategy.safety_multiplier * a.competitiveness())
-        )
-        .take((a.applications() as f32 * distribution.realistic) as usize)
-        .map(|p| p.id())
-        .collect();
-    let safety: Vec<u32> = programs.iter()
+    apply(&applicant.0, &plan.applications.0, programs, index, signals);
+    applicant.0.set_ranking(plan.rankings.0);
+    if let (Some(b), Some(applications), Some(ranking)) = (applicant.1.as_mut(), plan.applications.1, plan.rankings.1) {
+        apply(b, &applications, programs, index, signals);
+        b.set_ranking(ranking);
+    }
+    plan.shortfall
+}
+
+fn choose_programs<A, P>(a: &A, programs: &[P], strategy: &RankStrategy,
+                         distribution: &RankDistribution, fallback: bool) -> (Vec<u32>, usize)
+where A: HasApplications + HasSpecialty + Competitive,
+      P: Rankable<A> + HasSpecialty + Competitive
+{
+    let applications = a.applications() as usize;
+    let bounds = [
+        (strategy.reach_multiplier, strategy.realistic_multiplier),
+        (strategy.realistic_multiplier, strategy.safety_multiplier),
+        (strategy.safety_multiplier, 0.0),
+    ];
+    let bands: Vec<Vec<u32>> = bounds.iter().map(|(upper, lower)| programs.iter()
         .filter(|p|
-            p.competitiveness() < strategy.safety_multiplier * a.competitiveness()
+                p.specialty() == a.specialty() &&
+                    p.competitiveness() < upper * a.competitiveness() &&
+                    p.competitiveness() >= lower * a.competitiveness()
         )
-        .take((a.applications() as f32 * distribution.safety) as usize)
+        .take(applications)
         .map(|p| p.id())
+        .collect()
+    ).collect();
+
+    let wanted = distribution.split(applications);
+    let mut taken: Vec<usize> = wanted.iter().zip(&bands).map(|(w, b)| usize::min(*w, b.len())).collect();
+    let mut shortfall = applications.saturating_sub(taken.iter().sum::<usize>());
+    if fallback {
+        for band in [1, 2, 0] {
+            let extra = usize::min(shortfall, bands[band].len() - taken[band]);
+            taken[band] += extra;
+            shortfall -= extra;
+        }
+    }
+
+    let all = bands.iter().zip(taken)
+        .flat_map(|(b, n)| b.iter().take(n).copied())
         .collect();
-    let all = [reach, realistic, safety].concat();
-    for i in &all {
-        let p = programs.iter_mut().find(|p| p.id() == *i).unwrap();
-        a.add_ranking(p);
-        p.receive_application(&a);
-    }
-    if let Some(b) = applicant.1.as_mut() {
-        for i in all {
-            let p = programs.iter_mut().find(|p| p.id() == i).unwrap();
-            b.add_ranking(p);
-            p.receive_application(&b);
+    (all, shortfall)
+}
+
+fn apply<A, P>(a: &A, to: &[u32], programs: &mut [P], index: &HashMap<u32, usize>, signals: u8)
+where A: Competitive,
+      P: ReceiveApplication<A>
+{
+    for (n, i) in to.iter().enumerate() {
+        programs[index[i]].receive_application(a, n < signals as usize);
+    }
+}
+
+/// Whether a couple can rank the pair of programs: both partners can only go to the
+/// same program if it has room for two.
+fn compatible<A, P>(programs: &[P], p: u32, q: u32) -> bool
+where P: Rankable<A> + HasCapacity,
+      A: Competitive
+{
+    p != q || programs.iter().find(|program| program.id() == p).is_some_and(|program| program.capacity() >= 2)
+}
+
+/// Builds a couple's joint rank list out of each partner's own list, keeping at most `len`
+/// compatible pairs.
+///
+/// Pairs are ordered by the sum of the two partners' positions on their own lists, and
+/// pairs with the same sum are ordered so that the partners' positions are as even as possible.
+pub fn joint_ranking<F>(first: &[u32], second: &[u32], compatible: F, len: usize) -> Vec<(u32, u32)>
+where F: Fn(u32, u32) -> bool
+{
+    let mut pairs = Vec::new();
+    for sum in 0..(first.len() + second.len()).saturating_sub(1) {
+        let mut diagonal: Vec<(usize, usize)> = (0..=sum)
+            .filter(|i| *i < first.len() && sum - i < second.len())
+            .map(|i| (i, sum - i))
+            .collect();
+        diagonal.sort_by_key(|(i, j)| (i.abs_diff(*j), *i));
+        for (i, j) in diagonal {
+            if compatible(first[i], second[j]) {
+                pairs.push((first[i], second[j]));
+                if pairs.len() == len {
+                    return pairs;
+                }
+            }
         }
     }
+    pairs
+}
+
+/// A way of building the rank lists of applicants and programs.
+///
+/// The driver calls `prepare` once, then `plan_applicant` for every applicant (or couple),
+/// which only reads the programs and may run in parallel, then `submit_plan` for every
+/// applicant in order, and finally `rank_program` for every program, again in parallel.
+/// A strategy only has to describe how a single applicant or program builds its list.
+pub trait RankingStrategy<A, P>: Sync {
+    fn prepare(&self, _programs: &mut Vec<P>) {}
+    fn plan_applicant(&self, applicant: &Couple<A>, programs: &[P]) -> ApplicationPlan;
+    /// Applies the plan, returning how many of the applicant's applications could not
+    /// be made, so truncated lists can be reported.
+    fn submit_plan(&self, applicant: &mut Couple<A>, plan: ApplicationPlan,
+                   programs: &mut [P], index: &HashMap<u32, usize>) -> usize;
+    fn rank_program(&self, program: &mut P, applicants: &[&A]);
+}
+
+/// Applicants and programs both rank whoever is closest to their own competitiveness.
+pub struct NaiveRanking;
+
+impl<A, P> RankingStrategy<A, P> for NaiveRanking
+where A: Rankable<P> + HasApplications + HasSpecialty + Competitive,
+      P: Rankable<A> + HasCapacity + HasSpecialty + Competitive
+{
+    fn plan_applicant(&self, applicant: &Couple<A>, programs: &[P]) -> ApplicationPlan {
+        let closest = |a: &A| {
+            let mut candidates = programs.iter().filter(|p| p.specialty() == a.specialty()).collect::<Vec<_>>();
+            a.sort_by_closeness(&mut candidates);
+            let num = a.applications() as usize;
+            let shortfall = num.saturating_sub(candidates.len());
+            (candidates.iter().take(num).map(|p| p.id()).collect::<Vec<_>>(), shortfall)
+        };
+        let (first, shortfall) = closest(&applicant.0);
+        match &applicant.1 {
+            None => ApplicationPlan::single(first, shortfall),
+            Some(b) => {
+                let (second, short) = closest(b);
+                ApplicationPlan::couple::<A, P>(first, second, shortfall + short, programs)
+            }
+        }
+    }
+
+    fn submit_plan(&self, applicant: &mut Couple<A>, plan: ApplicationPlan,
+                   _programs: &mut [P], _index: &HashMap<u32, usize>) -> usize {
+        // programs rank every applicant of their specialty, so there is nothing to send
+        applicant.0.set_ranking(plan.rankings.0);
+        if let (Some(b), Some(ranking)) = (applicant.1.as_mut(), plan.rankings.1) {
+            b.set_ranking(ranking);
+        }
+        plan.shortfall
+    }
+
+    fn rank_program(&self, program: &mut P, applicants: &[&A]) {
+        let mut candidates = applicants.iter()
+            .filter(|a| a.specialty() == program.specialty())
+            .copied()
+            .collect::<Vec<_>>();
+        let num = program.capacity() as u32 * 15;
+        program.naive_rank(&mut candidates, num);
+    }
+}
+
+/// Applicants split their applications between reach, realistic and safety programs,
+/// signalling the first `signals` of them and falling back to other bands when one runs
+/// short if `fallback` is set, and programs rank the applications they received according
+/// to `program_policy`.
+pub struct TieredRanking {
+    pub strategy: RankStrategy,
+    pub distribution: RankDistribution,
+    pub signals: u8,
+    pub fallback: bool,
+    pub program_policy: ProgramRankPolicy,
+}
+
+impl Default for TieredRanking {
+    fn default() -> Self {
+        TieredRanking {
+            strategy: RankStrategy::new(1.05, 0.95, 0.90).unwrap(),
+            distribution: RankDistribution::new(0.3, 0.5, 0.2).unwrap(),
+            signals: 0,
+            fallback: false,
+            program_policy: ProgramRankPolicy::default(),
+        }
+    }
+}
+
+impl<A, P> RankingStrategy<A, P> for TieredRanking
+where A: Rankable<P> + HasApplications + HasSpecialty + Competitive,
+      P: Rankable<A> + ReceiveApplication<A> + HasCapacity + HasSpecialty + Competitive
+{
+    fn prepare(&self, programs: &mut Vec<P>) {
+        programs.sort_by(|a, b| b.competitiveness().total_cmp(&a.competitiveness()));
+    }
+
+    fn plan_applicant(&self, applicant: &Couple<A>, programs: &[P]) -> ApplicationPlan {
+        plan(applicant, programs, &self.strategy, &self.distribution, self.fallback)
+    }
+
+    fn submit_plan(&self, applicant: &mut Couple<A>, plan: ApplicationPlan,
+                   programs: &mut [P], index: &HashMap<u32, usize>) -> usize {
+        submit(applicant, plan, programs, index, self.signals)
+    }
+
+    fn rank_program(&self, program: &mut P, _applicants: &[&A]) {
+        program.process_applications(&self.program_policy)
+    }
 }
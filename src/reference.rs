@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use crate::models::{Couple, HasCapacity};
+use crate::ranker::Rankable;
+use crate::stability::Assignment;
+
+/// Everything a single applicant or couple could be matched to: each mutually ranked
+/// program (or pair of programs) in their order of preference, then going unmatched.
+fn options<A, P>(c: &Couple<A>, programs: &HashMap<u32, &P>) -> Vec<Vec<(u32, u32)>>
+where A: Rankable<P>,
+      P: Rankable<A> + HasCapacity
+{
+    let ranks = |p: u32, a: &A| programs.get(&p).is_some_and(|p| p.capacity() > 0 && p.ranking().contains(&a.id()));
+    let mut options: Vec<Vec<(u32, u32)>> = match &c.1 {
+        None => c.0.ranking().into_iter()
+            .filter(|p| ranks(*p, &c.0))
+            .map(|p| vec![(c.0.id(), p)])
+            .collect(),
+        Some(b) => c.0.ranking().into_iter().zip(b.ranking())
+            .filter(|(p, q)| ranks(*p, &c.0) && ranks(*q, b))
+            .map(|(p, q)| vec![(c.0.id(), p), (b.id(), q)])
+            .collect()
+    };
+    options.push(Vec::new());
+    options
+}
+
+/// Whether the program would choose all of `newcomers`, out of them and whoever it holds
+/// other than the `leaving` applicants, by filling its positions down its rank list.
+fn chooses<A, P>(program: &P, assigned: &Assignment, newcomers: &[u32], leaving: &[u32]) -> bool
+where A: Rankable<P>,
+      P: Rankable<A> + HasCapacity
+{
+    let ranking = program.ranking();
+    let holders = assigned.iter()
+        .filter(|(a, p)| **p == program.id() && !leaving.contains(a))
+        .map(|(a, _)| *a);
+    let mut candidates: Vec<Option<usize>> = holders.chain(newcomers.iter().copied())
+        .map(|a| ranking.iter().position(|r| *r == a))
+        .collect();
+    // anyone unranked goes first
+    candidates.sort_by_key(|rank| rank.unwrap_or(usize::MAX));
+    let chosen = &candidates[..usize::min(candidates.len(), program.capacity() as usize)];
+    newcomers.iter().all(|a| ranking.iter().position(|r| r == a).is_some_and(|rank| chosen.contains(&Some(rank))))
+}
+
+/// Whether no single applicant or couple would rather have one of their `options` than what
+/// they were assigned, with the programs in it choosing them. Written independently of
+/// `stability`, so the two can be checked against each other.
+fn is_stable<A, P>(applicants: &[Couple<A>], programs: &HashMap<u32, &P>, options: &[Vec<Vec<(u32, u32)>>], assigned: &Assignment) -> bool
+where A: Rankable<P>,
+      P: Rankable<A> + HasCapacity
+{
+    applicants.iter().zip(options).all(|(c, options)| {
+        let members: Vec<u32> = std::iter::once(c.0.id()).chain(c.1.as_ref().map(|b| b.id())).collect();
+        let current = options.iter()
+            .position(|o| match o.is_empty() {
+                true => members.iter().all(|a| !assigned.contains_key(a)),
+                false => o.iter().all(|(a, p)| assigned.get(a) == Some(p))
+            })
+            .unwrap_or(options.len());
+        options[..current].iter().all(|option| {
+            let mut wanted: HashMap<u32, Vec<u32>> = HashMap::new();
+            for (a, p) in option {
+                wanted.entry(*p).or_default().push(*a);
+            }
+            !wanted.iter().all(|(p, newcomers)| chooses::<A, P>(programs[p], assigned, newcomers, &members))
+        })
+    })
+}
+
+fn enumerate<A, P>(
+    applicants: &[Couple<A>],
+    programs: &HashMap<u32, &P>,
+    all_options: &[Vec<Vec<(u32, u32)>>],
+    options: &[Vec<Vec<(u32, u32)>>],
+    openings: &mut HashMap<u32, u16>,
+    assigned: &mut Assignment,
+    stable: &mut Vec<Assignment>
+)
+where A: Rankable<P>,
+      P: Rankable<A> + HasCapacity
+{
+    let (unit, rest) = match options.split_first() {
+        None => {
+            if is_stable(applicants, programs, all_options, assigned) {
+                stable.push(assigned.clone());
+            }
+            return
+        },
+        Some(o) => o
+    };
+    for option in unit {
+        if option.iter().all(|(_, p)| openings[p] >= option.iter().filter(|(_, q)| q == p).count() as u16) {
+            for (a, p) in option {
+                *openings.get_mut(p).unwrap() -= 1;
+                assigned.insert(*a, *p);
+            }
+            enumerate(applicants, programs, all_options, rest, openings, assigned, stable);
+            for (a, p) in option {
+                *openings.get_mut(p).unwrap() += 1;
+                assigned.remove(a);
+            }
+        }
+    }
+}
+
+/// Every stable matching of the instance, for checking `Matcher` against. With couples this
+/// may be empty.
+///
+/// Every assignment that respects capacities and rank lists is enumerated, so this is only
+/// usable with a handful of applicants and programs.
+pub fn stable_matchings<A, P>(applicants: &[Couple<A>], programs: &[P]) -> Vec<Assignment>
+where A: Rankable<P>,
+      P: Rankable<A> + HasCapacity
+{
+    let by_id: HashMap<u32, &P> = programs.iter().map(|p| (p.id(), p)).collect();
+    let options: Vec<_> = applicants.iter().map(|c| options(c, &by_id)).collect();
+    let mut openings = programs.iter().map(|p| (p.id(), p.capacity())).collect();
+    let mut stable = Vec::new();
+    enumerate(applicants, &by_id, &options, &options, &mut openings, &mut Assignment::new(), &mut stable);
+    stable
+}
+
+/// How far down their rank list (or joint rank list, for a couple) each single applicant
+/// or couple was matched; unmatched is past the end of the list.
+fn positions<A, P>(applicants: &[Couple<A>], assigned: &Assignment) -> Vec<usize>
+where A: Rankable<P>,
+      P: Rankable<A>
+{
+    applicants.iter().map(|c| match &c.1 {
+        None => {
+            let ranking = c.0.ranking();
+            assigned.get(&c.0.id())
+                .and_then(|p| ranking.iter().position(|r| r == p))
+                .unwrap_or(ranking.len())
+        },
+        Some(b) => {
+            let joint: Vec<(u32, u32)> = c.0.ranking().into_iter().zip(b.ranking()).collect();
+            match (assigned.get(&c.0.id()), assigned.get(&b.id())) {
+                (Some(p), Some(q)) => joint.iter().position(|r| *r == (*p, *q)).unwrap_or(joint.len()),
+                _ => joint.len()
+            }
+        }
+    }).collect()
+}
+
+/// The stable matching every applicant likes at least as much as any other, if there is one.
+/// Without couples there always is, provided `matchings` are all of the stable matchings.
+pub fn applicant_optimal<'m, A, P>(applicants: &[Couple<A>], matchings: &'m [Assignment]) -> Option<&'m Assignment>
+where A: Rankable<P>,
+      P: Rankable<A>
+{
+    let positions: Vec<Vec<usize>> = matchings.iter().map(|m| positions::<A, P>(applicants, m)).collect();
+    matchings.iter().zip(positions.iter())
+        .find(|(_, best)| positions.iter().all(|other| best.iter().zip(other).all(|(b, o)| b <= o)))
+        .map(|(m, _)| m)
+}
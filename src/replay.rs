@@ -0,0 +1,116 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::audit::MatchEvent;
+
+/// Who holds which places, and who has been left unmatched, at some point in a match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchState {
+    /// Applicants tentatively matched to each program, in order of id. Applicants holding a
+    /// preliminary position alongside an advanced program are under both.
+    pub holds: BTreeMap<u32, BTreeSet<u32>>,
+    /// Preliminary positions held alongside an advanced program, by applicant id.
+    pub linked: BTreeMap<u32, u32>,
+    pub unmatched: BTreeSet<u32>,
+}
+
+impl MatchState {
+    /// Moves the match on by one event.
+    pub fn apply(&mut self, event: &MatchEvent) {
+        match event {
+            MatchEvent::Accepted { proposal, .. } => {
+                self.holds.entry(proposal.program).or_default().insert(proposal.applicant);
+                self.unmatched.remove(&proposal.applicant);
+                if proposal.linked {
+                    self.linked.insert(proposal.applicant, proposal.program);
+                }
+            },
+            MatchEvent::Rejected { .. } => {},
+            MatchEvent::Displaced { applicant, program, .. } | MatchEvent::Withdrawn { applicant, program } => {
+                if let Some(held) = self.holds.get_mut(program) {
+                    held.remove(applicant);
+                    if held.is_empty() {
+                        self.holds.remove(program);
+                    }
+                }
+                if self.linked.get(applicant) == Some(program) {
+                    self.linked.remove(applicant);
+                }
+            },
+            MatchEvent::GaveUp { applicant } | MatchEvent::Exhausted { applicant } => {
+                self.unmatched.insert(*applicant);
+            },
+            MatchEvent::Reentered { applicant } => {
+                // anywhere they held is given up in events of its own
+                self.unmatched.remove(applicant);
+            }
+        }
+    }
+
+    /// Where each applicant holds a place, leaving out preliminary positions as
+    /// `stability::assignment` does.
+    pub fn assignment(&self) -> BTreeMap<u32, u32> {
+        self.holds.iter()
+            .flat_map(|(p, held)| held.iter().map(move |a| (*a, *p)))
+            .filter(|(a, p)| self.linked.get(a) != Some(p))
+            .collect()
+    }
+}
+
+/// Steps through a match's events, to look at the match as it was after any of them.
+pub struct Replayer<'e> {
+    events: &'e [MatchEvent],
+    position: usize,
+    state: MatchState,
+}
+
+impl<'e> Replayer<'e> {
+    pub fn new(events: &'e [MatchEvent]) -> Replayer<'e> {
+        Replayer { events, position: 0, state: MatchState::default() }
+    }
+
+    /// How many events have been replayed.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn state(&self) -> &MatchState {
+        &self.state
+    }
+
+    /// Replays the next event, returning it, or `None` at the end of the match.
+    pub fn step(&mut self) -> Option<&'e MatchEvent> {
+        let event = self.events.get(self.position)?;
+        self.state.apply(event);
+        self.position += 1;
+        Some(event)
+    }
+
+    /// Moves to just after the first `position` events (or the end of the match, if there
+    /// are fewer). Going back starts over from the beginning.
+    pub fn seek(&mut self, position: usize) -> &MatchState {
+        if position < self.position {
+            self.position = 0;
+            self.state = MatchState::default();
+        }
+        while self.position < position && self.step().is_some() {}
+        &self.state
+    }
+
+    /// Replays events until `stop` is true of one, returning it, or `None` if it never is.
+    pub fn run_until<F>(&mut self, mut stop: F) -> Option<&'e MatchEvent>
+    where F: FnMut(&MatchEvent) -> bool
+    {
+        while let Some(event) = self.step() {
+            if stop(event) {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
+
+/// The match as it was after the first `position` events.
+pub fn state_at(events: &[MatchEvent], position: usize) -> MatchState {
+    let mut replayer = Replayer::new(events);
+    replayer.seek(position);
+    replayer.state
+}
@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use rand::Rng;
+use crate::matcher::{InstabilityResolution, MatchError, Matcher};
+use crate::models::{generator, Applicant, Couple, Program};
+use crate::outcome::{self, MatchOutcome, OutcomeDiff};
+use crate::parameters::MatchParameters;
+use crate::ranker;
+use crate::stability::{self, Assignment};
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    UnknownTransformation(String),
+    InvalidValue(String),
+}
+
+impl std::fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ScenarioError::UnknownTransformation(s) => write!(f, "Unknown transformation: {}", s),
+            ScenarioError::InvalidValue(s) => write!(f, "Invalid value: {}", s),
+        }
+    }
+}
+
+/// A change to a loaded match, to see how the outcome would have been different.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transformation {
+    /// Multiplies the share of applicants in couples, pairing up single applicants at random
+    /// or splitting couples up. A new couple ranks pairs of the programs on its partners'
+    /// own rank lists; a partner left on their own ranks their programs from the couple's list.
+    CoupleRate(f64),
+    /// Gives every program this many more positions (fewer, if negative). A program with
+    /// tracks gets them in its first track.
+    Capacity(i32),
+    /// Multiplies the number of programs every applicant applied to. Applicants drop (or
+    /// keep) the programs they wanted least, and programs drop them in turn.
+    Applications(f64),
+}
+
+impl Transformation {
+    /// Reads a transformation written as `couples=<factor>`, `capacity=<change>` or
+    /// `applications=<factor>`, e.g. `couples=2` or `capacity=+1`.
+    pub fn parse(s: &str) -> Result<Transformation, ScenarioError> {
+        let (name, value) = s.split_once('=').ok_or(ScenarioError::UnknownTransformation(s.to_string()))?;
+        let factor = || value.parse::<f64>().ok().filter(|f| *f >= 0.0).ok_or(ScenarioError::InvalidValue(s.to_string()));
+        match name {
+            "couples" => Ok(Transformation::CoupleRate(factor()?)),
+            "capacity" => value.trim_start_matches('+').parse::<i32>()
+                .map(Transformation::Capacity)
+                .map_err(|_| ScenarioError::InvalidValue(s.to_string())),
+            "applications" => Ok(Transformation::Applications(factor()?)),
+            _ => Err(ScenarioError::UnknownTransformation(s.to_string()))
+        }
+    }
+}
+
+/// Transformations applied, in order, to a loaded match.
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    pub transformations: Vec<Transformation>,
+}
+
+impl Scenario {
+    pub fn parse(transformations: &[&str]) -> Result<Scenario, ScenarioError> {
+        let transformations = transformations.iter().map(|t| Transformation::parse(t)).collect::<Result<_, _>>()?;
+        Ok(Scenario { transformations })
+    }
+
+    /// Applies the transformations, making any random choices with `seed`.
+    pub fn apply(&self, parameters: &mut MatchParameters, seed: u64) {
+        for (i, transformation) in self.transformations.iter().enumerate() {
+            match *transformation {
+                Transformation::CoupleRate(factor) => change_couple_rate(parameters, factor, seed ^ i as u64),
+                Transformation::Capacity(change) => change_capacity(&mut parameters.programs, change),
+                Transformation::Applications(factor) => change_applications(parameters, factor)
+            }
+        }
+    }
+}
+
+fn change_capacity(programs: &mut [Program], change: i32) {
+    let add = |capacity: u16| (capacity as i32 + change).clamp(0, u16::MAX as i32) as u16;
+    for p in programs.iter_mut() {
+        p.capacity = add(p.capacity);
+        if let Some(track) = p.tracks.first_mut() {
+            track.capacity = add(track.capacity);
+        }
+    }
+}
+
+/// Programs in the order they first come up on a rank list.
+fn programs_ranked(ranking: &[u32]) -> Vec<u32> {
+    let mut seen = HashSet::new();
+    ranking.iter().copied().filter(|p| seen.insert(*p)).collect()
+}
+
+fn change_couple_rate(parameters: &mut MatchParameters, factor: f64, seed: u64) {
+    let mut rng = generator::seeded_rng(seed);
+    let people = parameters.applicants.iter().map(|c| 1 + c.1.is_some() as usize).sum::<usize>();
+    let coupled = parameters.applicants.iter().filter(|c| c.1.is_some()).count() * 2;
+    let wanted = usize::min((coupled as f64 * factor).round() as usize, people) / 2 * 2;
+
+    // the units to pair up or split, in a random order
+    let (mut candidates, rest): (Vec<Couple<Applicant>>, Vec<Couple<Applicant>>) = std::mem::take(&mut parameters.applicants)
+        .into_iter()
+        .partition(|c| (c.1.is_some()) == (wanted < coupled));
+    let mut keyed: Vec<(u64, Couple<Applicant>)> = candidates.drain(..).map(|c| (rng.random(), c)).collect();
+    keyed.sort_by_key(|(k, _)| *k);
+    let mut candidates = keyed.into_iter().map(|(_, c)| c);
+
+    let mut changed: Vec<Couple<Applicant>> = Vec::new();
+    if wanted < coupled {
+        for _ in 0..(coupled - wanted) / 2 {
+            if let Some(Couple(mut a, Some(mut b))) = candidates.next() {
+                a.couple = None;
+                b.couple = None;
+                a.ranking = programs_ranked(&a.ranking);
+                b.ranking = programs_ranked(&b.ranking);
+                changed.push(Couple(a, None));
+                changed.push(Couple(b, None));
+            }
+        }
+    } else {
+        let programs = &parameters.programs;
+        for _ in 0..(wanted - coupled) / 2 {
+            let (mut a, mut b) = match (candidates.next(), candidates.next()) {
+                (Some(Couple(a, None)), Some(Couple(b, None))) => (a, b),
+                (last, _) => {
+                    // one single applicant left over
+                    changed.extend(last);
+                    break;
+                }
+            };
+            a.couple = Some(b.id);
+            b.couple = Some(a.id);
+            let compatible = |p: u32, q: u32| p != q || programs.iter().find(|program| program.id == p).is_some_and(|program| program.capacity >= 2);
+            let pairs = ranker::joint_ranking(&a.ranking, &b.ranking, compatible, a.ranking.len() + b.ranking.len());
+            (a.ranking, b.ranking) = pairs.into_iter().unzip();
+            a.supplemental.clear();
+            b.supplemental.clear();
+            changed.push(Couple(a, Some(b)));
+        }
+    }
+    parameters.applicants = rest.into_iter().chain(changed).chain(candidates).collect();
+    parameters.applicants.sort_by_key(|c| c.0.id);
+
+    let couples: HashMap<u32, Option<u32>> = parameters.applicants.iter()
+        .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+        .map(|a| (a.id, a.couple))
+        .collect();
+    for application in parameters.programs.iter_mut().flat_map(|p| p.applications.iter_mut()) {
+        if let Some(couple) = couples.get(&application.applicant) {
+            application.couple = *couple;
+        }
+    }
+}
+
+/// Takes applicants off a program's rank list (and its tracks'), keeping its tiers.
+fn drop_applicants(program: &mut Program, dropped: &HashSet<u32>) {
+    if !program.tiers.is_empty() {
+        let mut rest = program.ranking.as_slice();
+        for size in program.tiers.iter_mut() {
+            let (tier, after) = rest.split_at(usize::min(*size as usize, rest.len()));
+            *size -= tier.iter().filter(|a| dropped.contains(a)).count() as u32;
+            rest = after;
+        }
+        program.tiers.retain(|size| *size > 0);
+    }
+    program.ranking.retain(|a| !dropped.contains(a));
+    program.applications.retain(|a| !dropped.contains(&a.applicant));
+    for track in program.tracks.iter_mut() {
+        track.ranking.retain(|a| !dropped.contains(a));
+    }
+}
+
+fn change_applications(parameters: &mut MatchParameters, factor: f64) {
+    // applicants dropped by each program
+    let mut dropped: HashMap<u32, HashSet<u32>> = HashMap::new();
+    let scale = |n: usize| (n as f64 * factor).round() as usize;
+    for c in parameters.applicants.iter_mut() {
+        let kept: Vec<HashSet<u32>> = std::iter::once(&c.0).chain(c.1.as_ref())
+            .map(|a| {
+                let programs = programs_ranked(&a.ranking);
+                let keep = scale(programs.len());
+                for p in programs.iter().skip(keep) {
+                    dropped.entry(*p).or_default().insert(a.id);
+                }
+                programs.into_iter().take(keep).collect()
+            })
+            .collect();
+        match c.1.as_mut() {
+            None => c.0.ranking.retain(|p| kept[0].contains(p)),
+            Some(b) => {
+                let joint: Vec<(u32, u32)> = c.0.ranking.iter().copied().zip(b.ranking.iter().copied())
+                    .filter(|(p, q)| kept[0].contains(p) && kept[1].contains(q))
+                    .collect();
+                (c.0.ranking, b.ranking) = joint.into_iter().unzip();
+            }
+        }
+        for a in std::iter::once(&mut c.0).chain(c.1.as_mut()) {
+            a.applications = scale(a.applications as usize).min(u16::MAX as usize) as u16;
+            let ranking = &a.ranking;
+            a.supplemental.retain(|advanced, _| ranking.contains(advanced));
+        }
+    }
+    for p in parameters.programs.iter_mut() {
+        if let Some(dropped) = dropped.get(&p.id) {
+            drop_applicants(p, dropped);
+        }
+    }
+}
+
+/// How a match came out, each applicant judged by their own rank list.
+#[derive(Debug, Clone, Default)]
+pub struct OutcomeSummary {
+    pub applicants: usize,
+    pub couples: usize,
+    pub positions: u32,
+    pub matched: usize,
+    pub couples_matched: usize,
+    /// Applicants matched to the first program on their rank list (for a partner in a
+    /// couple, their half of the joint rank list).
+    pub first_choice: usize,
+    /// Average position of matched applicants' programs on their rank lists, counting from 1.
+    pub mean_choice: f32,
+    pub unfilled_positions: u32,
+}
+
+impl OutcomeSummary {
+    pub fn new(parameters: &MatchParameters, assigned: &Assignment) -> OutcomeSummary {
+        let applicants: Vec<&Applicant> = parameters.applicants.iter()
+            .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+            .collect();
+        let choices: Vec<usize> = applicants.iter()
+            .filter_map(|a| assigned.get(&a.id).and_then(|p| a.ranking.iter().position(|q| q == p)))
+            .collect();
+        let positions = parameters.programs.iter().map(|p| p.capacity as u32).sum::<u32>();
+        OutcomeSummary {
+            applicants: applicants.len(),
+            couples: parameters.applicants.iter().filter(|c| c.1.is_some()).count(),
+            positions,
+            matched: assigned.len(),
+            couples_matched: parameters.applicants.iter()
+                .filter(|c| c.1.as_ref().is_some_and(|b| assigned.contains_key(&b.id)) && assigned.contains_key(&c.0.id))
+                .count(),
+            first_choice: choices.iter().filter(|i| **i == 0).count(),
+            mean_choice: choices.iter().map(|i| *i as f32 + 1.0).sum::<f32>() / choices.len().max(1) as f32,
+            unfilled_positions: positions.saturating_sub(assigned.len() as u32),
+        }
+    }
+}
+
+/// A match run as it was and as a scenario would have it.
+#[derive(Debug, Clone)]
+pub struct ScenarioComparison {
+    pub baseline: OutcomeSummary,
+    pub counterfactual: OutcomeSummary,
+    /// Applicants in both runs who matched differently, including matching or not.
+    pub changed: usize,
+    pub baseline_assigned: Assignment,
+    pub counterfactual_assigned: Assignment,
+    /// Who matched differently and which programs filled differently, with applicants
+    /// judged by their rank lists in the baseline.
+    pub diff: OutcomeDiff,
+}
+
+/// Matches `parameters` as they are and with `scenario` applied, and compares the two.
+pub fn run_scenario(parameters: &MatchParameters, scenario: &Scenario, seed: u64) -> Result<ScenarioComparison, MatchError> {
+    let mut counterfactual = parameters.clone();
+    scenario.apply(&mut counterfactual, seed);
+    let run = |parameters: &MatchParameters| -> Result<Assignment, MatchError> {
+        let mut matcher = Matcher::new();
+        matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess)?;
+        Ok(stability::assignment(&matcher))
+    };
+    let baseline_assigned = run(parameters)?;
+    let counterfactual_assigned = run(&counterfactual)?;
+    let diff = outcome::diff(
+        &MatchOutcome::new(parameters, &baseline_assigned),
+        &MatchOutcome::new(&counterfactual, &counterfactual_assigned)
+    );
+    Ok(ScenarioComparison {
+        baseline: OutcomeSummary::new(parameters, &baseline_assigned),
+        counterfactual: OutcomeSummary::new(&counterfactual, &counterfactual_assigned),
+        changed: diff.changes.len(),
+        baseline_assigned,
+        counterfactual_assigned,
+        diff,
+    })
+}
@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+use crate::matcher::Matcher;
+use crate::models::{HasCapacity, HasCouple, HasSpecialty, HasSupplemental};
+use crate::ranker::Rankable;
+use crate::stability::Assignment;
+
+/// How the supplemental offer round after the match runs.
+#[derive(Debug, Clone)]
+pub struct SoapPolicy {
+    /// Most programs with open positions an unmatched applicant applies to.
+    pub applications: usize,
+    /// Number of rounds of offers.
+    pub rounds: usize,
+}
+
+impl Default for SoapPolicy {
+    /// Up to 45 applications and four rounds of offers.
+    fn default() -> Self {
+        SoapPolicy {
+            applications: 45,
+            rounds: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SoapRound {
+    pub offers: usize,
+    /// Offers accepted, which is the number of positions filled in the round.
+    pub accepted: usize,
+    /// Offers turned down, either for a better offer or to wait for one.
+    pub declined: usize,
+    /// Applicants who turned down every offer they got, holding out for a program they prefer.
+    pub waiting: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SoapOutcome {
+    pub rounds: Vec<SoapRound>,
+    /// Where applicants were placed by the offer rounds.
+    pub placed: Assignment,
+    /// Positions still open after the last round.
+    pub unfilled: u32,
+}
+
+/// Applicants left unmatched by the match, and programs with open positions, in the
+/// supplemental offer round that follows it.
+struct Scramble<'m, A, P> {
+    applicants: Vec<&'m A>,
+    programs: Vec<&'m P>,
+    openings: Vec<u32>,
+    /// Programs each applicant applied to (as indices of `programs`), most wanted first.
+    applications: Vec<Vec<usize>>,
+    /// Applicants who applied to each program (as indices of `applicants`), strongest first.
+    applicants_of: Vec<Vec<usize>>,
+}
+
+impl<'m, A, P> Scramble<'m, A, P>
+where A: Rankable<P> + HasSpecialty,
+      P: Rankable<A> + HasCapacity + HasSpecialty
+{
+    fn new(applicants: Vec<&'m A>, open: Vec<(&'m P, u32)>, policy: &SoapPolicy) -> Scramble<'m, A, P> {
+        let (programs, openings): (Vec<&P>, Vec<u32>) = open.into_iter().unzip();
+        let index: HashMap<u32, usize> = programs.iter().enumerate().map(|(i, p)| (p.id(), i)).collect();
+
+        // applicants go back to the open programs they ranked in the match, then try the
+        // programs of their specialty closest to them in competitiveness
+        let applications: Vec<Vec<usize>> = applicants.iter().map(|a| {
+            let mut applied: Vec<usize> = a.ranking().iter().filter_map(|p| index.get(p).copied()).collect();
+            let ranked: HashSet<usize> = applied.iter().copied().collect();
+            let mut nearby: Vec<usize> = (0..programs.len())
+                .filter(|p| !ranked.contains(p) && programs[*p].specialty() == a.specialty())
+                .collect();
+            nearby.sort_by(|p, q| {
+                let distance = |p: &usize| (programs[*p].competitiveness() - a.competitiveness()).abs();
+                distance(p).total_cmp(&distance(q)).then(p.cmp(q))
+            });
+            applied.extend(nearby);
+            applied.truncate(policy.applications);
+            applied
+        }).collect();
+
+        let mut applicants_of = vec![Vec::new(); programs.len()];
+        for (a, applied) in applications.iter().enumerate() {
+            for p in applied {
+                applicants_of[*p].push(a);
+            }
+        }
+        for list in applicants_of.iter_mut() {
+            list.sort_by(|a, b| applicants[*b].competitiveness().total_cmp(&applicants[*a].competitiveness()).then(a.cmp(b)));
+        }
+
+        Scramble { applicants, programs, openings, applications, applicants_of }
+    }
+
+    fn run(&mut self, policy: &SoapPolicy) -> SoapOutcome {
+        let mut outcome = SoapOutcome::default();
+        let mut placed: Vec<Option<usize>> = vec![None; self.applicants.len()];
+        // offers are never repeated, whether they were declined or not
+        let mut offered: HashSet<(usize, usize)> = HashSet::new();
+
+        for round in 0..policy.rounds {
+            let mut offers: Vec<Vec<usize>> = vec![Vec::new(); self.applicants.len()];
+            let mut made = 0;
+            for (p, list) in self.applicants_of.iter().enumerate() {
+                let candidates: Vec<usize> = list.iter()
+                    .filter(|a| placed[**a].is_none() && !offered.contains(&(p, **a)))
+                    .take(self.openings[p] as usize)
+                    .copied()
+                    .collect();
+                for a in candidates {
+                    offered.insert((p, a));
+                    offers[a].push(p);
+                    made += 1;
+                }
+            }
+            if made == 0 {
+                break;
+            }
+
+            // applicants hold out for programs near the top of their list in the early rounds,
+            // settling for anything they applied to by the last one
+            let patience = self.applications.iter()
+                .map(|applied| match round + 1 == policy.rounds {
+                    true => applied.len(),
+                    false => (applied.len() * (round + 1)).div_ceil(policy.rounds)
+                });
+            let mut result = SoapRound { offers: made, ..SoapRound::default() };
+            for (a, cutoff) in patience.enumerate() {
+                if offers[a].is_empty() {
+                    continue;
+                }
+                let rank = |p: &usize| self.applications[a].iter().position(|q| q == p).unwrap();
+                let best = *offers[a].iter().min_by_key(|p| rank(p)).unwrap();
+                result.declined += offers[a].len() - 1;
+                if rank(&best) < cutoff {
+                    placed[a] = Some(best);
+                    self.openings[best] -= 1;
+                    result.accepted += 1;
+                } else {
+                    result.declined += 1;
+                    result.waiting += 1;
+                }
+            }
+            outcome.rounds.push(result);
+        }
+
+        outcome.placed = placed.iter().enumerate()
+            .filter_map(|(a, p)| p.map(|p| (self.applicants[a].id(), self.programs[p].id())))
+            .collect();
+        outcome.unfilled = self.openings.iter().sum();
+        outcome
+    }
+}
+
+pub fn run_soap<A, P>(matcher: &Matcher<'_, A, P>) -> SoapOutcome
+where A: Rankable<P> + HasCouple + HasSupplemental + HasSpecialty + Clone,
+      P: Rankable<A> + HasCapacity + HasSpecialty
+{
+    run_soap_(matcher, &SoapPolicy::default())
+}
+
+/// Simulates the supplemental offer round after a match: applicants the match left
+/// unmatched apply to programs with open positions, and programs offer their open
+/// positions to the strongest applicants in rounds, which applicants accept or turn down.
+///
+/// Partners of a couple are placed on their own, as they would be after the match.
+pub fn run_soap_<A, P>(matcher: &Matcher<'_, A, P>, policy: &SoapPolicy) -> SoapOutcome
+where A: Rankable<P> + HasCouple + HasSupplemental + HasSpecialty + Clone,
+      P: Rankable<A> + HasCapacity + HasSpecialty
+{
+    let open: Vec<(&P, u32)> = matcher.matches.iter()
+        .map(|m| (m.0, (m.0.capacity() as usize).saturating_sub(m.1.len()) as u32))
+        .chain(matcher.unmatched_p.iter().map(|p| (*p, p.capacity() as u32)))
+        .filter(|(_, openings)| *openings > 0)
+        .collect();
+    let applicants = matcher.unmatched_a.to_vec();
+    Scramble::new(applicants, open, policy).run(policy)
+}
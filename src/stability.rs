@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use crate::matcher::Matcher;
+use crate::models::{Couple, HasCapacity, HasCouple, HasSupplemental};
+use crate::ranker::{Competitive, Rankable};
+
+/// An applicant (or couple) and program (or pair of programs) that would both rather be
+/// matched to each other than keep what the match gave them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockingPair {
+    Single { applicant: u32, program: u32 },
+    Couple { applicants: (u32, u32), programs: (u32, u32) },
+    /// An applicant matched to an advanced program and a preliminary program on their
+    /// supplemental rank list for it they would rather have than their preliminary position.
+    Supplemental { applicant: u32, program: u32 },
+}
+
+/// The program each matched applicant was placed with, by applicant id.
+pub type Assignment = HashMap<u32, u32>;
+
+/// The assignment a finished match made. Preliminary positions linked to an advanced
+/// program are left out, and are in `Matcher::linked` instead.
+pub fn assignment<A, P>(matcher: &Matcher<A, P>) -> Assignment
+where A: Rankable<P> + HasCouple + HasSupplemental + Clone,
+      P: Rankable<A> + HasCapacity
+{
+    matcher.matches.iter()
+        .flat_map(|m| m.1.iter().map(move |a| (a.id(), m.0.id())))
+        .filter(|(a, p)| matcher.linked.get(a) != Some(p))
+        .collect()
+}
+
+/// Programs' rank lists and capacities alongside who each program matched.
+struct Placements<'m> {
+    ranks: HashMap<u32, HashMap<u32, usize>>,
+    capacities: HashMap<u32, u16>,
+    holders: HashMap<u32, Vec<u32>>,
+    assigned: &'m Assignment,
+}
+
+impl<'m> Placements<'m> {
+    /// `linked` preliminary positions take up places like any other, but are not anyone's match.
+    fn new<A, P>(programs: &[P], assigned: &'m Assignment, linked: &Assignment) -> Placements<'m>
+    where P: Rankable<A> + HasCapacity,
+          A: Competitive
+    {
+        let ranks = programs.iter()
+            .map(|p| (p.id(), p.ranking().into_iter().enumerate().map(|(i, a)| (a, i)).collect()))
+            .collect();
+        let capacities = programs.iter().map(|p| (p.id(), p.capacity())).collect();
+        let mut holders: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (a, p) in assigned.iter().chain(linked) {
+            holders.entry(*p).or_default().push(*a);
+        }
+        Placements { ranks, capacities, holders, assigned }
+    }
+
+    fn rank(&self, program: u32, applicant: u32) -> Option<usize> {
+        self.ranks.get(&program).and_then(|r| r.get(&applicant).copied())
+    }
+
+    /// Who the program would have to give up places from, if the `leaving` applicants left it.
+    fn others(&self, program: u32, leaving: &[u32]) -> Vec<usize> {
+        self.holders.get(&program).into_iter().flatten()
+            .filter(|h| !leaving.contains(h))
+            // an applicant the program did not rank is the first to go
+            .map(|h| self.rank(program, *h).unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Whether the program would take `applicant`, if the `leaving` applicants left it.
+    fn accepts(&self, program: u32, applicant: u32, leaving: &[u32]) -> bool {
+        let rank = match self.rank(program, applicant) {
+            None => return false,
+            Some(r) => r
+        };
+        let capacity = self.capacities[&program] as usize;
+        let others = self.others(program, leaving);
+        capacity > 0 && (others.len() < capacity || others.iter().any(|r| *r > rank))
+    }
+
+    /// Whether the program would take both partners of a couple, if the `leaving` applicants left it.
+    fn accepts_both(&self, program: u32, applicants: (u32, u32), leaving: &[u32]) -> bool {
+        let rank = match (self.rank(program, applicants.0), self.rank(program, applicants.1)) {
+            (Some(r0), Some(r1)) => usize::max(r0, r1),
+            _ => return false
+        };
+        let capacity = self.capacities[&program] as usize;
+        let others = self.others(program, leaving);
+        let displaced = (others.len() + 2).saturating_sub(capacity);
+        capacity >= 2 && others.iter().filter(|r| **r > rank).count() >= displaced
+    }
+}
+
+/// Every blocking pair of a finished match.
+///
+/// A single applicant blocks with a program they rank above their match (or rank at all, if
+/// unmatched) when that program ranked them and has an opening or holds someone it ranked
+/// lower. A couple blocks with a pair of programs earlier on their joint rank list when each
+/// program would take its partner, after the couple gives up the places it holds. A single
+/// applicant matched to an advanced program blocks with a preliminary program the same way,
+/// going by their supplemental rank list and the preliminary position they hold.
+pub fn blocking_pairs<A, P>(applicants: &[Couple<A>], programs: &[P], matcher: &Matcher<A, P>) -> Vec<BlockingPair>
+where A: Rankable<P> + HasCouple + HasSupplemental + Clone,
+      P: Rankable<A> + HasCapacity
+{
+    linked_blocking_pairs(applicants, programs, &assignment(matcher), &matcher.linked)
+}
+
+/// Every blocking pair of an assignment, as defined for `blocking_pairs`.
+pub fn assignment_blocking_pairs<A, P>(applicants: &[Couple<A>], programs: &[P], assigned: &Assignment) -> Vec<BlockingPair>
+where A: Rankable<P> + HasSupplemental,
+      P: Rankable<A> + HasCapacity
+{
+    linked_blocking_pairs(applicants, programs, assigned, &Assignment::new())
+}
+
+fn linked_blocking_pairs<A, P>(applicants: &[Couple<A>], programs: &[P], assigned: &Assignment, linked: &Assignment) -> Vec<BlockingPair>
+where A: Rankable<P> + HasSupplemental,
+      P: Rankable<A> + HasCapacity
+{
+    let placements = Placements::new::<A, P>(programs, assigned, linked);
+    let mut blocking = Vec::new();
+    for c in applicants {
+        match &c.1 {
+            None => {
+                let a = c.0.id();
+                let ranking = c.0.ranking();
+                let current = placements.assigned.get(&a)
+                    .and_then(|p| ranking.iter().position(|r| r == p))
+                    .unwrap_or(ranking.len());
+                blocking.extend(ranking[..current].iter()
+                    .filter(|p| placements.accepts(**p, a, &[]))
+                    .map(|p| BlockingPair::Single { applicant: a, program: *p }));
+
+                if let Some(supplemental) = placements.assigned.get(&a).and_then(|p| c.0.supplemental(*p)) {
+                    let current = linked.get(&a)
+                        .and_then(|q| supplemental.iter().position(|r| r == q))
+                        .unwrap_or(supplemental.len());
+                    blocking.extend(supplemental[..current].iter()
+                        .filter(|q| placements.accepts(**q, a, &[]))
+                        .map(|q| BlockingPair::Supplemental { applicant: a, program: *q }));
+                }
+            },
+            Some(b) => {
+                let applicants = (c.0.id(), b.id());
+                let joint: Vec<(u32, u32)> = c.0.ranking().into_iter().zip(b.ranking()).collect();
+                let current = match (placements.assigned.get(&applicants.0), placements.assigned.get(&applicants.1)) {
+                    (Some(p), Some(q)) => joint.iter().position(|r| *r == (*p, *q)).unwrap_or(joint.len()),
+                    _ => joint.len()
+                };
+                let leaving = [applicants.0, applicants.1];
+                blocking.extend(joint[..current].iter()
+                    .filter(|(p, q)| match p == q {
+                        true => placements.accepts_both(*p, applicants, &leaving),
+                        false => placements.accepts(*p, applicants.0, &leaving)
+                            && placements.accepts(*q, applicants.1, &leaving)
+                    })
+                    .map(|programs| BlockingPair::Couple { applicants, programs: *programs }));
+            }
+        }
+    }
+    blocking
+}
+
+/// Whether a finished match has no blocking pairs.
+pub fn is_stable<A, P>(applicants: &[Couple<A>], programs: &[P], matcher: &Matcher<A, P>) -> bool
+where A: Rankable<P> + HasCouple + HasSupplemental + Clone,
+      P: Rankable<A> + HasCapacity
+{
+    blocking_pairs(applicants, programs, matcher).is_empty()
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use rand::Rng;
+use crate::matcher::{MatchError, Matcher};
+use crate::models::{generator, Program};
+use crate::parameters::MatchParameters;
+use crate::stability::{self, Assignment};
+
+/// How ties in programs' rank lists are broken before the match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreaking {
+    /// Every applicant draws one lottery number, which every program goes by.
+    #[default]
+    SingleLottery,
+    /// Every program draws its own lottery numbers.
+    MultipleLotteries,
+    /// Applicants who ranked the program higher on their own rank list go first, and a
+    /// single lottery breaks the ties that are left.
+    ApplicantPriority,
+}
+
+impl TieBreaking {
+    pub const ALL: [TieBreaking; 3] = [TieBreaking::SingleLottery, TieBreaking::MultipleLotteries, TieBreaking::ApplicantPriority];
+}
+
+/// The tiers of a program's rank list, best first. A program that ranked in a strict order
+/// has a tier for every applicant.
+pub fn tiers(program: &Program) -> Vec<&[u32]> {
+    if program.tiers.is_empty() {
+        return program.ranking.chunks(1).collect();
+    }
+    let mut rest = program.ranking.as_slice();
+    program.tiers.iter().map(|size| {
+        let (tier, after) = rest.split_at(usize::min(*size as usize, rest.len()));
+        rest = after;
+        tier
+    }).collect()
+}
+
+/// Lottery numbers for the applicants, drawn in order of their ids so the same seed always
+/// gives an applicant the same number.
+fn lottery(mut applicants: Vec<u32>, seed: u64) -> HashMap<u32, u64> {
+    applicants.sort_unstable();
+    let mut rng = generator::seeded_rng(seed);
+    applicants.into_iter().map(|a| (a, rng.random())).collect()
+}
+
+/// Orders the applicants within each tier of every program's rank list by `rule`. The tiers
+/// are kept, so ties can be broken again another way.
+pub fn break_ties(parameters: &mut MatchParameters, rule: TieBreaking, seed: u64) {
+    let applicants = parameters.applicants.iter().flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()));
+    let single = lottery(applicants.clone().map(|a| a.id).collect(), seed);
+    // where each applicant put each program on their own rank list
+    let priority: HashMap<(u32, u32), usize> = match rule {
+        TieBreaking::ApplicantPriority => applicants
+            .flat_map(|a| a.ranking.iter().enumerate().rev().map(move |(i, p)| ((a.id, *p), i)))
+            .collect(),
+        _ => HashMap::new()
+    };
+
+    for program in parameters.programs.iter_mut().filter(|p| !p.tiers.is_empty()) {
+        let own;
+        let numbers = match rule {
+            TieBreaking::MultipleLotteries => {
+                own = lottery(program.ranking.clone(), seed ^ program.id as u64);
+                &own
+            },
+            _ => &single
+        };
+        let key = |a: &u32| (
+            priority.get(&(*a, program.id)).copied().unwrap_or(usize::MAX),
+            numbers.get(a).copied().unwrap_or(u64::MAX),
+            *a
+        );
+        let mut ranking: Vec<u32> = Vec::with_capacity(program.ranking.len());
+        for tier in tiers(program) {
+            let mut tier = tier.to_vec();
+            tier.sort_by_key(key);
+            ranking.extend(tier);
+        }
+        program.ranking = ranking;
+    }
+}
+
+/// How a match came out with ties broken by `rule`.
+#[derive(Debug, Clone)]
+pub struct TieBreakingOutcome {
+    pub rule: TieBreaking,
+    pub matched: usize,
+    /// Applicants matched to the first program (or pair of programs) on their rank list.
+    pub first_choice: usize,
+    /// Average position of matched applicants' programs on their rank lists, counting from 1.
+    pub mean_choice: f32,
+    /// Applicants matched differently than with ties broken by a single lottery.
+    pub changed: usize,
+    pub assigned: Assignment,
+}
+
+/// Runs the match once with ties broken by each rule, for comparing how the rules change
+/// who matches where.
+pub fn compare_tie_breaking(parameters: &MatchParameters, seed: u64) -> Result<Vec<TieBreakingOutcome>, MatchError> {
+    let choices: HashMap<u32, &Vec<u32>> = parameters.applicants.iter()
+        .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+        .map(|a| (a.id, &a.ranking))
+        .collect();
+
+    let mut outcomes: Vec<TieBreakingOutcome> = Vec::new();
+    for rule in TieBreaking::ALL {
+        let mut broken = parameters.clone();
+        break_ties(&mut broken, rule, seed);
+        let mut matcher = Matcher::new();
+        matcher.run_match(&broken.applicants, &broken.programs)?;
+        let assigned = stability::assignment(&matcher);
+
+        let positions: Vec<usize> = assigned.iter()
+            .filter_map(|(a, p)| choices[a].iter().position(|q| q == p))
+            .collect();
+        let changed = match outcomes.first() {
+            None => 0,
+            Some(reference) => choices.keys().filter(|a| assigned.get(a) != reference.assigned.get(a)).count()
+        };
+        outcomes.push(TieBreakingOutcome {
+            rule,
+            matched: assigned.len(),
+            first_choice: positions.iter().filter(|i| **i == 0).count(),
+            mean_choice: positions.iter().map(|i| *i as f32 + 1.0).sum::<f32>() / positions.len().max(1) as f32,
+            changed,
+            assigned,
+        });
+    }
+    Ok(outcomes)
+}
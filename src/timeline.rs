@@ -0,0 +1,78 @@
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use crate::models::{Applicant, Program};
+use crate::parameters::MatchParameters;
+
+/// A program left out of the match because it didn't certify its rank list by its deadline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Excluded {
+    pub program: u32,
+    pub name: String,
+    pub institution: String,
+    pub deadline: DateTime<Utc>,
+    /// When the program certified its rank list, if it did at all.
+    pub certified: Option<DateTime<Utc>>,
+    /// Applicants who ranked the program, and lost it from their rank list.
+    pub ranked_by: Vec<u32>,
+}
+
+/// Whether the program certified its rank list by its deadline.
+pub fn is_certified(program: &Program) -> bool {
+    program.certified.is_some_and(|c| c <= program.deadline)
+}
+
+/// Removes programs that didn't certify their rank lists in time from the match, along
+/// with every place applicants ranked them: a couple loses each pair of programs with one
+/// of them in it, and supplemental rank lists for them are dropped.
+pub fn exclude_late(parameters: &mut MatchParameters) -> Vec<Excluded> {
+    let (programs, late): (Vec<Program>, Vec<Program>) = std::mem::take(&mut parameters.programs)
+        .into_iter()
+        .partition(is_certified);
+    parameters.programs = programs;
+    parameters.num_programs = parameters.programs.len();
+    if late.is_empty() {
+        return Vec::new();
+    }
+
+    let mut excluded: Vec<Excluded> = late.into_iter().map(|p| Excluded {
+        program: p.id,
+        name: p.name,
+        institution: p.institution,
+        deadline: p.deadline,
+        certified: p.certified,
+        ranked_by: Vec::new(),
+    }).collect();
+    let late: HashSet<u32> = excluded.iter().map(|e| e.program).collect();
+    let is_late = |p: &u32| late.contains(p);
+    let mut ranked_by: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut note = |a: &Applicant| for p in a.ranking.iter().filter(|p| is_late(p)) {
+        ranked_by.entry(*p).or_default().push(a.id);
+    };
+
+    for c in parameters.applicants.iter_mut() {
+        note(&c.0);
+        match c.1.as_mut() {
+            None => c.0.ranking.retain(|p| !is_late(p)),
+            Some(b) => {
+                note(b);
+                let joint: Vec<(u32, u32)> = c.0.ranking.iter().copied().zip(b.ranking.iter().copied())
+                    .filter(|(p, q)| !is_late(p) && !is_late(q))
+                    .collect();
+                (c.0.ranking, b.ranking) = joint.into_iter().unzip();
+            }
+        }
+        for a in std::iter::once(&mut c.0).chain(c.1.as_mut()) {
+            a.supplemental.retain(|advanced, _| !is_late(advanced));
+            for preliminary in a.supplemental.values_mut() {
+                preliminary.retain(|p| !is_late(p));
+            }
+        }
+    }
+
+    for e in excluded.iter_mut() {
+        e.ranked_by = ranked_by.remove(&e.program).unwrap_or_default();
+        e.ranked_by.sort_unstable();
+        e.ranked_by.dedup();
+    }
+    excluded
+}
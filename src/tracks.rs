@@ -0,0 +1,107 @@
+use std::collections::{HashMap, HashSet};
+use crate::matcher::{MatchError, Matcher};
+use crate::models::{Applicant, Couple, Program};
+use crate::parameters::Delta;
+use crate::stability::{self, Assignment};
+
+/// Positions a track left unfilled, moved to the track they revert to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reversion {
+    /// Round of reversion the positions were moved in, counting from 1.
+    pub round: usize,
+    pub from: u32,
+    pub to: u32,
+    pub positions: u16,
+}
+
+/// Splits programs into their tracks, each matched as a program of its own. Programs
+/// without tracks are kept as they are.
+pub fn track_programs(programs: &[Program]) -> Vec<Program> {
+    programs.iter().flat_map(|p| match p.tracks.is_empty() {
+        true => vec![p.clone()],
+        false => p.tracks.iter().map(|t| Program {
+            id: t.id,
+            name: format!("{} (track {})", p.name, t.id),
+            institution: p.institution.clone(),
+            deadline: p.deadline,
+            certified: p.certified,
+            capacity: t.capacity,
+            competitiveness: p.competitiveness,
+            specialty: p.specialty,
+            position_type: p.position_type,
+            applications: Vec::new(),
+            ranking: t.ranking.clone(),
+            tiers: Vec::new(),
+            tracks: Vec::new(),
+        }).collect()
+    }).collect()
+}
+
+/// The track each track reverts its unfilled positions to, by track id.
+pub fn reversion_rules(programs: &[Program]) -> HashMap<u32, u32> {
+    programs.iter()
+        .flat_map(|p| &p.tracks)
+        .filter_map(|t| t.reverts_to.map(|to| (t.id, to)))
+        .collect()
+}
+
+fn run_match(applicants: &Vec<Couple<Applicant>>, tracks: &Vec<Program>) -> Result<Assignment, MatchError> {
+    let mut matcher = Matcher::new();
+    matcher.run_match(applicants, tracks)?;
+    Ok(stability::assignment(&matcher))
+}
+
+fn rematch(applicants: &Vec<Couple<Applicant>>, tracks: &Vec<Program>, previous: &Assignment, delta: &Delta) -> Result<Assignment, MatchError> {
+    let mut matcher = Matcher::new();
+    matcher.rematch(applicants, tracks, previous, delta)?;
+    Ok(stability::assignment(&matcher))
+}
+
+/// Matches applicants to `tracks` (as split by `track_programs`), then moves the positions
+/// tracks left unfilled to the tracks they revert to under `rules`, catching the match up
+/// with each move, until no track that can revert its positions has any unfilled.
+///
+/// Each track reverts at most once, so positions can't go back and forth between tracks
+/// that revert to each other. `tracks` is left with the capacities the positions ended up in.
+pub fn revert_unfilled(applicants: &Vec<Couple<Applicant>>, tracks: &mut Vec<Program>,
+                       rules: &HashMap<u32, u32>) -> Result<Vec<Reversion>, MatchError> {
+    let mut assigned = run_match(applicants, tracks)?;
+    let mut reverted: HashSet<u32> = HashSet::new();
+    let mut reversions = Vec::new();
+    let index: HashMap<u32, usize> = tracks.iter().enumerate().map(|(i, t)| (t.id, i)).collect();
+
+    for round in 1.. {
+        let mut filled: HashMap<u32, usize> = HashMap::new();
+        for t in assigned.values() {
+            *filled.entry(*t).or_insert(0) += 1;
+        }
+        let mut moves: Vec<(u32, u32, u16)> = tracks.iter()
+            .filter(|t| !reverted.contains(&t.id))
+            .filter_map(|t| {
+                let to = *rules.get(&t.id)?;
+                let positions = t.capacity.saturating_sub(filled.get(&t.id).copied().unwrap_or(0) as u16);
+                (positions > 0 && index.contains_key(&to)).then_some((t.id, to, positions))
+            })
+            .collect();
+        if moves.is_empty() {
+            break;
+        }
+        moves.sort_unstable();
+
+        for (from, to, positions) in moves {
+            reverted.insert(from);
+            // a track can't take more positions than a capacity can hold
+            let positions = u16::min(positions, u16::MAX - tracks[index[&to]].capacity);
+            if positions == 0 {
+                continue;
+            }
+            // the positions were unfilled, so taking them away from a track moves no one
+            tracks[index[&from]].capacity -= positions;
+            tracks[index[&to]].capacity += positions;
+            let delta = Delta::Capacity { program: to, capacity: tracks[index[&to]].capacity };
+            assigned = rematch(applicants, tracks, &assigned, &delta)?;
+            reversions.push(Reversion { round, from, to, positions });
+        }
+    }
+    Ok(reversions)
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use proptest::prelude::*;
+use residency_match::audit::{self, Outcome, Rejection};
+use residency_match::matcher::{InstabilityResolution, Matcher};
+use residency_match::stability;
+use common::match_parameters;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn recording_events_does_not_change_the_match(parameters in match_parameters(10, 6, 0.3)) {
+        let mut matcher = Matcher::new();
+        matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess).unwrap();
+        prop_assert!(!matcher.events.is_on());
+        prop_assert!(audit::explain(&parameters.applicants, &matcher, parameters.applicants[0].0.id).is_none());
+
+        let mut recorded = Matcher::new();
+        recorded.record_events();
+        recorded.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess).unwrap();
+        prop_assert!(recorded.events.recorded().is_some());
+        prop_assert_eq!(stability::assignment(&recorded), stability::assignment(&matcher));
+    }
+
+    #[test]
+    fn every_choice_above_the_match_is_explained(parameters in match_parameters(10, 6, 0.3)) {
+        let mut matcher = Matcher::new();
+        matcher.record_events();
+        matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess).unwrap();
+        let assigned = stability::assignment(&matcher);
+
+        for a in parameters.applicants.iter().flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref())) {
+            let explanation = audit::explain(&parameters.applicants, &matcher, a.id).unwrap();
+            prop_assert_eq!(explanation.matched, assigned.get(&a.id).copied());
+            prop_assert_eq!(explanation.choices.iter().map(|c| c.program).collect::<Vec<_>>(), a.ranking.clone());
+
+            let matched_at = explanation.choices.iter().position(|c| c.outcome == Outcome::Matched);
+            prop_assert_eq!(matched_at.map(|i| explanation.choices[i].program), explanation.matched,
+                            "applicant {} explained as matched elsewhere: {:?}", a.id, explanation);
+            for choice in explanation.choices[..matched_at.unwrap_or(explanation.choices.len())].iter() {
+                match &choice.outcome {
+                    Outcome::Matched | Outcome::NotReached =>
+                        prop_assert!(false, "applicant {} has no reason for program {}: {:?}", a.id, choice.program, explanation),
+                    Outcome::Rejected(Rejection::Full { rank, weakest_rank }) => prop_assert!(rank > weakest_rank),
+                    Outcome::Displaced { rank, by_rank, .. } => prop_assert!(by_rank < rank),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
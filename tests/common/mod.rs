@@ -0,0 +1,223 @@
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use chrono::DateTime;
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+use residency_match::models::{Applicant, Couple, MatchStatus, PositionType, Program, Specialty, Track};
+use residency_match::parameters::MatchParameters;
+
+pub fn applicant(id: u32, couple: Option<u32>, ranking: Vec<u32>) -> Applicant {
+    Applicant {
+        id,
+        status: MatchStatus::Certified,
+        applications: ranking.len() as u16,
+        competitiveness: 0.5,
+        specialty: Specialty::InternalMedicine,
+        couple,
+        ranking,
+        supplemental: HashMap::new(),
+        profile: None,
+    }
+}
+
+pub fn program(id: u32, capacity: u16, ranking: Vec<u32>) -> Program {
+    typed_program(id, capacity, PositionType::Categorical, ranking)
+}
+
+fn typed_program(id: u32, capacity: u16, position_type: PositionType, ranking: Vec<u32>) -> Program {
+    Program {
+        id,
+        name: format!("Program {}", id),
+        institution: String::new(),
+        deadline: DateTime::default(),
+        certified: Some(DateTime::default()),
+        capacity,
+        competitiveness: 0.5,
+        specialty: Specialty::InternalMedicine,
+        position_type,
+        applications: Vec::new(),
+        ranking,
+        tiers: Vec::new(),
+        tracks: Vec::new(),
+    }
+}
+
+/// Rank lists for a single applicant, or joint rank lists for a couple (each pair of
+/// programs at most once).
+fn rankings(num_programs: usize, coupled: bool) -> BoxedStrategy<(Vec<u32>, Option<Vec<u32>>)> {
+    let programs: Vec<u32> = (0..num_programs as u32).collect();
+    match coupled {
+        false => subsequence(programs, 0..=num_programs)
+            .prop_shuffle()
+            .prop_map(|r| (r, None))
+            .boxed(),
+        true => prop::collection::vec((0..num_programs as u32, 0..num_programs as u32), 0..=2 * num_programs)
+            .prop_map(|pairs| {
+                let mut joint: Vec<(u32, u32)> = Vec::new();
+                for pair in pairs {
+                    if !joint.contains(&pair) {
+                        joint.push(pair);
+                    }
+                }
+                let (first, second) = joint.into_iter().unzip();
+                (first, Some(second))
+            })
+            .boxed()
+    }
+}
+
+/// Small random matches: up to `max_units` single applicants or couples (each a couple with
+/// probability `couple_rate`) and up to `max_programs` programs with capacities up to 3.
+///
+/// Applicants rank random programs and programs rank random applicants, so rank lists need
+/// not be mutual.
+pub fn match_parameters(max_units: usize, max_programs: usize, couple_rate: f64) -> impl Strategy<Value = MatchParameters> {
+    (1..=max_units, 1..=max_programs, prop::collection::vec(prop::bool::weighted(couple_rate), max_units))
+        .prop_flat_map(|(num_units, num_programs, coupled)| {
+            let coupled = coupled[..num_units].to_vec();
+            let num_applicants = num_units + coupled.iter().filter(|c| **c).count();
+            let applicants: Vec<u32> = (0..num_applicants as u32).collect();
+            let programs = prop::collection::vec(
+                (0..=3u16, subsequence(applicants.clone(), 0..=num_applicants).prop_shuffle()),
+                num_programs
+            );
+            let rankings: Vec<_> = coupled.iter().map(|c| rankings(num_programs, *c)).collect();
+            (Just(num_applicants), programs, rankings)
+        })
+        .prop_map(|(num_applicants, programs, rankings)| {
+            let mut id = 0u32;
+            let applicants = rankings.into_iter().map(|(first, second)| {
+                let couple = match second {
+                    None => Couple(applicant(id, None, first), None),
+                    Some(second) => Couple(applicant(id, Some(id + 1), first), Some(applicant(id + 1, Some(id), second)))
+                };
+                id += if couple.1.is_some() { 2 } else { 1 };
+                couple
+            }).collect();
+            let programs: Vec<Program> = programs.into_iter().enumerate()
+                .map(|(id, (capacity, ranking))| program(id as u32, capacity, ranking))
+                .collect();
+            MatchParameters {
+                num_applicants,
+                num_programs: programs.len(),
+                applicants,
+                programs
+            }
+        })
+}
+
+/// Small random matches without couples where everyone ranks everyone on the other side and
+/// there are as many applicants as positions, which tends to have many stable matchings.
+pub fn complete_match_parameters(max_programs: usize) -> impl Strategy<Value = MatchParameters> {
+    prop::collection::vec(1..=2u16, 1..=max_programs)
+        .prop_flat_map(|capacities| {
+            let num_applicants: usize = capacities.iter().map(|c| *c as usize).sum();
+            let applicants: Vec<u32> = (0..num_applicants as u32).collect();
+            let programs: Vec<u32> = (0..capacities.len() as u32).collect();
+            let rankings: Vec<_> = capacities.iter().map(|_| Just(applicants.clone()).prop_shuffle()).collect();
+            (
+                prop::collection::vec(Just(programs).prop_shuffle(), num_applicants),
+                Just(capacities),
+                rankings
+            )
+        })
+        .prop_map(|(rankings, capacities, program_rankings)| MatchParameters {
+            num_applicants: rankings.len(),
+            num_programs: capacities.len(),
+            applicants: rankings.into_iter().enumerate()
+                .map(|(id, ranking)| Couple(applicant(id as u32, None, ranking), None))
+                .collect(),
+            programs: capacities.into_iter().zip(program_rankings).enumerate()
+                .map(|(id, (capacity, ranking))| program(id as u32, capacity, ranking))
+                .collect()
+        })
+}
+
+/// Small random matches without couples where programs may be preliminary or advanced.
+/// Applicants rank any programs, with a supplemental rank list of preliminary programs for
+/// each advanced program they rank.
+pub fn linked_match_parameters(max_applicants: usize, max_programs: usize) -> impl Strategy<Value = MatchParameters> {
+    let position_type = prop_oneof![Just(PositionType::Categorical), Just(PositionType::Preliminary), Just(PositionType::Advanced)];
+    (1..=max_applicants, prop::collection::vec(position_type, 1..=max_programs))
+        .prop_flat_map(|(num_applicants, types)| {
+            let applicants: Vec<u32> = (0..num_applicants as u32).collect();
+            // applicants can rank preliminary programs directly too, for just the one year
+            let main: Vec<u32> = (0..types.len() as u32).collect();
+            let preliminary: Vec<u32> = (0..types.len() as u32).filter(|p| types[*p as usize] == PositionType::Preliminary).collect();
+            let programs = prop::collection::vec(
+                (0..=3u16, subsequence(applicants.clone(), 0..=num_applicants).prop_shuffle()),
+                types.len()
+            );
+            let rankings = prop::collection::vec(
+                (
+                    subsequence(main.clone(), 0..=main.len()).prop_shuffle(),
+                    prop::collection::vec(subsequence(preliminary.clone(), 0..=preliminary.len()).prop_shuffle(), types.len())
+                ),
+                num_applicants
+            );
+            (Just(types), programs, rankings)
+        })
+        .prop_map(|(types, programs, rankings)| {
+            let applicants: Vec<Couple<Applicant>> = rankings.into_iter().enumerate().map(|(id, (ranking, supplemental))| {
+                let mut a = applicant(id as u32, None, ranking);
+                a.supplemental = a.ranking.iter()
+                    .filter(|p| types[**p as usize] == PositionType::Advanced)
+                    .map(|p| (*p, supplemental[*p as usize].clone()))
+                    .collect();
+                Couple(a, None)
+            }).collect();
+            let programs: Vec<Program> = programs.into_iter().zip(types).enumerate()
+                .map(|(id, ((capacity, ranking), position_type))| typed_program(id as u32, capacity, position_type, ranking))
+                .collect();
+            MatchParameters {
+                num_applicants: applicants.len(),
+                num_programs: programs.len(),
+                applicants,
+                programs
+            }
+        })
+}
+
+/// Small random matches as for `match_parameters`, with the programs grouped into programs
+/// of one or more tracks each. A track may revert to another track of its program.
+pub fn tracked_match_parameters(max_units: usize, max_programs: usize, couple_rate: f64) -> impl Strategy<Value = MatchParameters> {
+    match_parameters(max_units, max_programs, couple_rate)
+        .prop_flat_map(|parameters| {
+            let num_tracks = parameters.programs.len();
+            let groups = prop::collection::vec((prop::bool::ANY, prop::option::of(0..num_tracks)), num_tracks);
+            (Just(parameters), groups)
+        })
+        .prop_map(|(parameters, groups)| {
+            let mut programs: Vec<Program> = Vec::new();
+            let mut members: Vec<Vec<Track>> = Vec::new();
+            for (p, (starts, _)) in parameters.programs.iter().zip(groups.iter()) {
+                if *starts || members.is_empty() {
+                    members.push(Vec::new());
+                }
+                members.last_mut().unwrap().push(Track {
+                    id: p.id,
+                    capacity: p.capacity,
+                    ranking: p.ranking.clone(),
+                    reverts_to: None,
+                });
+            }
+            let mut i = 0;
+            for (id, mut tracks) in members.into_iter().enumerate() {
+                let ids: Vec<u32> = tracks.iter().map(|t| t.id).collect();
+                for t in tracks.iter_mut() {
+                    t.reverts_to = groups[i].1.map(|r| ids[r % ids.len()]).filter(|r| *r != t.id);
+                    i += 1;
+                }
+                let mut parent = program(parameters.programs.len() as u32 + id as u32, 0, Vec::new());
+                parent.tracks = tracks;
+                programs.push(parent);
+            }
+            MatchParameters {
+                num_applicants: parameters.num_applicants,
+                num_programs: programs.len(),
+                applicants: parameters.applicants,
+                programs
+            }
+        })
+}
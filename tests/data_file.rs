@@ -0,0 +1,409 @@
+mod common;
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use proptest::prelude::*;
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use residency_match::models::{Applicant, Application, Couple, MatchStatus, PositionType, Profile, Program, SchoolType, Specialty, Track};
+use residency_match::parameters::MatchParameters;
+use common::{match_parameters, tracked_match_parameters};
+
+/// A path in the temporary directory no other test case writes to.
+fn data_file() -> std::path::PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!("residency_match_{}_{}.bin", std::process::id(), NEXT.fetch_add(1, Ordering::SeqCst)))
+}
+
+/// The layout of data files written before versions were recorded, as the first release
+/// wrote them: capacities and application counts as `u8`, and no specialties, position
+/// types, supplemental rank lists or tracks.
+#[derive(Serialize)]
+struct V1Applicant {
+    id: u32,
+    applications: u8,
+    competitiveness: f32,
+    couple: Option<u32>,
+    ranking: Vec<u32>,
+}
+
+#[derive(Serialize)]
+struct V1Program {
+    id: u32,
+    capacity: u8,
+    competitiveness: f32,
+    applications: Vec<(u32, f32)>,
+    ranking: Vec<u32>,
+}
+
+#[derive(Serialize)]
+struct V1MatchParameters {
+    applicants: Vec<Couple<V1Applicant>>,
+    programs: Vec<V1Program>,
+    num_programs: usize,
+    num_applicants: usize,
+}
+
+/// The layout of version 2 data files, before applicants had profiles.
+#[derive(Serialize)]
+struct V2Applicant {
+    id: u32,
+    applications: u16,
+    competitiveness: f32,
+    specialty: Specialty,
+    couple: Option<u32>,
+    ranking: Vec<u32>,
+    supplemental: HashMap<u32, Vec<u32>>,
+}
+
+#[derive(Serialize)]
+struct V2MatchParameters {
+    applicants: Vec<Couple<V2Applicant>>,
+    programs: Vec<V3Program>,
+    num_programs: usize,
+    num_applicants: usize,
+}
+
+fn v2(parameters: &MatchParameters) -> V2MatchParameters {
+    let applicant = |a: &Applicant| V2Applicant {
+        id: a.id,
+        applications: a.applications,
+        competitiveness: a.competitiveness,
+        specialty: a.specialty,
+        couple: a.couple,
+        ranking: a.ranking.clone(),
+        supplemental: a.supplemental.clone(),
+    };
+    V2MatchParameters {
+        applicants: parameters.applicants.iter().map(|c| Couple(applicant(&c.0), c.1.as_ref().map(applicant))).collect(),
+        programs: parameters.programs.iter().map(v3_program).collect(),
+        num_programs: parameters.num_programs,
+        num_applicants: parameters.num_applicants,
+    }
+}
+
+/// The layout of version 3 data files, before programs had names, institutions and deadlines.
+#[derive(Serialize)]
+struct V3Program {
+    id: u32,
+    capacity: u16,
+    competitiveness: f32,
+    specialty: Specialty,
+    position_type: PositionType,
+    applications: Vec<Application>,
+    ranking: Vec<u32>,
+    tracks: Vec<Track>,
+}
+
+#[derive(Serialize)]
+struct V3MatchParameters {
+    applicants: Vec<Couple<V4Applicant>>,
+    programs: Vec<V3Program>,
+    num_programs: usize,
+    num_applicants: usize,
+}
+
+fn v3_program(p: &Program) -> V3Program {
+    V3Program {
+        id: p.id,
+        capacity: p.capacity,
+        competitiveness: p.competitiveness,
+        specialty: p.specialty,
+        position_type: p.position_type,
+        applications: p.applications.clone(),
+        ranking: p.ranking.clone(),
+        tracks: p.tracks.clone(),
+    }
+}
+
+fn v3(parameters: &MatchParameters) -> V3MatchParameters {
+    V3MatchParameters {
+        applicants: v4_applicants(parameters),
+        programs: parameters.programs.iter().map(v3_program).collect(),
+        num_programs: parameters.num_programs,
+        num_applicants: parameters.num_applicants,
+    }
+}
+
+/// The layout of version 4 data files, before applicants had match statuses.
+#[derive(Serialize)]
+struct V4Applicant {
+    id: u32,
+    applications: u16,
+    competitiveness: f32,
+    specialty: Specialty,
+    couple: Option<u32>,
+    ranking: Vec<u32>,
+    supplemental: HashMap<u32, Vec<u32>>,
+    profile: Option<Profile>,
+}
+
+#[derive(Serialize)]
+struct V4MatchParameters {
+    applicants: Vec<Couple<V4Applicant>>,
+    programs: Vec<V5Program>,
+    num_programs: usize,
+    num_applicants: usize,
+}
+
+fn v4_applicants(parameters: &MatchParameters) -> Vec<Couple<V4Applicant>> {
+    let applicant = |a: &Applicant| V4Applicant {
+        id: a.id,
+        applications: a.applications,
+        competitiveness: a.competitiveness,
+        specialty: a.specialty,
+        couple: a.couple,
+        ranking: a.ranking.clone(),
+        supplemental: a.supplemental.clone(),
+        profile: a.profile.clone(),
+    };
+    parameters.applicants.iter().map(|c| Couple(applicant(&c.0), c.1.as_ref().map(applicant))).collect()
+}
+
+fn v4(parameters: &MatchParameters) -> V4MatchParameters {
+    V4MatchParameters {
+        applicants: v4_applicants(parameters),
+        programs: parameters.programs.iter().map(v5_program).collect(),
+        num_programs: parameters.num_programs,
+        num_applicants: parameters.num_applicants,
+    }
+}
+
+/// The layout of version 5 data files, before program rank lists could have ties.
+#[derive(Serialize)]
+struct V5Program {
+    id: u32,
+    name: String,
+    institution: String,
+    deadline: DateTime<Utc>,
+    certified: Option<DateTime<Utc>>,
+    capacity: u16,
+    competitiveness: f32,
+    specialty: Specialty,
+    position_type: PositionType,
+    applications: Vec<Application>,
+    ranking: Vec<u32>,
+    tracks: Vec<Track>,
+}
+
+#[derive(Serialize)]
+struct V5MatchParameters {
+    applicants: Vec<Couple<Applicant>>,
+    programs: Vec<V5Program>,
+    num_programs: usize,
+    num_applicants: usize,
+}
+
+fn v5_program(p: &Program) -> V5Program {
+    V5Program {
+        id: p.id,
+        name: p.name.clone(),
+        institution: p.institution.clone(),
+        deadline: p.deadline,
+        certified: p.certified,
+        capacity: p.capacity,
+        competitiveness: p.competitiveness,
+        specialty: p.specialty,
+        position_type: p.position_type,
+        applications: p.applications.clone(),
+        ranking: p.ranking.clone(),
+        tracks: p.tracks.clone(),
+    }
+}
+
+fn v5(parameters: &MatchParameters) -> V5MatchParameters {
+    V5MatchParameters {
+        applicants: parameters.applicants.clone(),
+        programs: parameters.programs.iter().map(v5_program).collect(),
+        num_programs: parameters.num_programs,
+        num_applicants: parameters.num_applicants,
+    }
+}
+
+/// The parameters as opened from a file written before program rank lists could have ties.
+fn without_tiers(mut parameters: MatchParameters) -> MatchParameters {
+    for p in parameters.programs.iter_mut() {
+        p.tiers.clear();
+    }
+    parameters
+}
+
+/// The parameters as opened from a file written before applicants had match statuses (or
+/// rank lists ties), so anyone with a rank list counts as having gone into the match.
+fn without_statuses(mut parameters: MatchParameters) -> MatchParameters {
+    for a in parameters.applicants.iter_mut().flat_map(|c| std::iter::once(&mut c.0).chain(c.1.as_mut())) {
+        a.status = match a.ranking.is_empty() {
+            true => MatchStatus::Registered,
+            false => MatchStatus::Certified
+        };
+    }
+    without_tiers(parameters)
+}
+
+/// The parameters as opened from a file written before programs had names, institutions
+/// and deadlines (or applicants match statuses).
+fn without_metadata(mut parameters: MatchParameters) -> MatchParameters {
+    for p in parameters.programs.iter_mut() {
+        p.name = String::new();
+        p.institution = String::new();
+        p.deadline = DateTime::default();
+        p.certified = Some(DateTime::default());
+    }
+    without_statuses(parameters)
+}
+
+/// Writes `parameters` with the header of the given version of the data file format.
+fn write_versioned<T: Serialize>(path: &std::path::Path, version: u32, parameters: &T) {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
+    file.write_all(b"RMDF").unwrap();
+    bincode::serialize_into(&mut file, &version).unwrap();
+    bincode::serialize_into(&mut file, parameters).unwrap();
+    file.flush().unwrap();
+}
+
+fn profile() -> impl Strategy<Value = Profile> {
+    ("[A-Z][a-z]{1,8}", 25..=35u8, 200..=280u16, prop::sample::select(SchoolType::ALL.to_vec()), prop::bool::ANY)
+        .prop_map(|(name, age, exam_score, school, visa)| Profile {
+            email: format!("{}@example.com", name.to_lowercase()),
+            phone: "555-555-5555".to_string(),
+            name,
+            age,
+            school,
+            exam_score,
+            visa,
+        })
+}
+
+/// Parameters where some applicants have profiles, and programs rank in a single tier.
+fn profiled_parameters() -> impl Strategy<Value = MatchParameters> {
+    tracked_match_parameters(10, 6, 0.3)
+        .prop_flat_map(|parameters| {
+            let profiles = prop::collection::vec(prop::option::of(profile()), 2 * parameters.applicants.len());
+            (Just(parameters), profiles)
+        })
+        .prop_map(|(mut parameters, mut profiles)| {
+            for p in parameters.programs.iter_mut().filter(|p| !p.ranking.is_empty()) {
+                p.tiers = vec![p.ranking.len() as u32];
+            }
+            for c in parameters.applicants.iter_mut() {
+                c.0.profile = profiles.pop().unwrap();
+                if let Some(b) = c.1.as_mut() {
+                    b.profile = profiles.pop().unwrap();
+                }
+            }
+            parameters
+        })
+}
+
+/// Parameters only the first release's layout can hold: categorical programs in one
+/// specialty without tracks, applications neither signalled nor interviewed, and no
+/// supplemental rank lists.
+fn v1_parameters() -> impl Strategy<Value = MatchParameters> {
+    match_parameters(10, 6, 0.3)
+        .prop_flat_map(|parameters| {
+            let applied = parameters.programs.iter()
+                .map(|p| prop::collection::vec(0.0..1.0f32, p.ranking.len()))
+                .collect::<Vec<_>>();
+            (Just(parameters), applied)
+        })
+        .prop_map(|(mut parameters, applied)| {
+            let partners: HashMap<u32, u32> = parameters.applicants.iter()
+                .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+                .filter_map(|a| a.couple.map(|b| (a.id, b)))
+                .collect();
+            for (p, competitiveness) in parameters.programs.iter_mut().zip(applied) {
+                p.applications = p.ranking.iter().zip(competitiveness)
+                    .map(|(a, competitiveness)| Application {
+                        applicant: *a,
+                        competitiveness,
+                        couple: partners.get(a).copied(),
+                        signal: false,
+                        interviewed: false,
+                    })
+                    .collect();
+            }
+            parameters
+        })
+}
+
+fn v1(parameters: &MatchParameters) -> V1MatchParameters {
+    let applicant = |a: &Applicant| V1Applicant {
+        id: a.id,
+        applications: a.applications as u8,
+        competitiveness: a.competitiveness,
+        couple: a.couple,
+        ranking: a.ranking.clone(),
+    };
+    V1MatchParameters {
+        applicants: parameters.applicants.iter().map(|c| Couple(applicant(&c.0), c.1.as_ref().map(applicant))).collect(),
+        programs: parameters.programs.iter().map(|p| V1Program {
+            id: p.id,
+            capacity: p.capacity as u8,
+            competitiveness: p.competitiveness,
+            applications: p.applications.iter().map(|a| (a.applicant, a.competitiveness)).collect(),
+            ranking: p.ranking.clone(),
+        }).collect(),
+        num_programs: parameters.num_programs,
+        num_applicants: parameters.num_applicants,
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn saved_parameters_open_unchanged(parameters in profiled_parameters()) {
+        let path = data_file();
+        parameters.save(path.to_str().unwrap()).unwrap();
+        let opened = MatchParameters::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        prop_assert_eq!(format!("{:?}", opened.unwrap()), format!("{:?}", parameters));
+    }
+
+    #[test]
+    fn version_1_files_are_migrated(parameters in v1_parameters()) {
+        let path = data_file();
+        let file = std::fs::File::create(&path).unwrap();
+        bincode::serialize_into(std::io::BufWriter::new(file), &v1(&parameters)).unwrap();
+        let opened = MatchParameters::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        prop_assert_eq!(format!("{:?}", opened.unwrap()), format!("{:?}", without_metadata(parameters)));
+    }
+
+    #[test]
+    fn version_2_files_are_migrated(parameters in tracked_match_parameters(10, 6, 0.3)) {
+        let path = data_file();
+        write_versioned(&path, 2, &v2(&parameters));
+        let opened = MatchParameters::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        prop_assert_eq!(format!("{:?}", opened.unwrap()), format!("{:?}", without_metadata(parameters)));
+    }
+
+    #[test]
+    fn version_3_files_are_migrated(parameters in profiled_parameters()) {
+        let path = data_file();
+        write_versioned(&path, 3, &v3(&parameters));
+        let opened = MatchParameters::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        prop_assert_eq!(format!("{:?}", opened.unwrap()), format!("{:?}", without_metadata(parameters)));
+    }
+
+    #[test]
+    fn version_4_files_are_migrated(parameters in profiled_parameters()) {
+        let path = data_file();
+        write_versioned(&path, 4, &v4(&parameters));
+        let opened = MatchParameters::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        prop_assert_eq!(format!("{:?}", opened.unwrap()), format!("{:?}", without_statuses(parameters)));
+    }
+
+    #[test]
+    fn version_5_files_are_migrated(parameters in profiled_parameters()) {
+        let path = data_file();
+        write_versioned(&path, 5, &v5(&parameters));
+        let opened = MatchParameters::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        prop_assert_eq!(format!("{:?}", opened.unwrap()), format!("{:?}", without_tiers(parameters)));
+    }
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use proptest::prelude::*;
+use residency_match::lattice::{self, StableLattice};
+use residency_match::matcher::Matcher;
+use residency_match::models::{Applicant, Program};
+use residency_match::reference;
+use residency_match::stability;
+use residency_match::parameters::MatchParameters;
+use common::{complete_match_parameters, match_parameters};
+
+/// Instances with short rank lists, and instances with complete rank lists (which have more
+/// stable matchings).
+fn instances() -> impl Strategy<Value = MatchParameters> {
+    prop_oneof![match_parameters(6, 4, 0.0), complete_match_parameters(3)]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn lattice_finds_every_stable_matching(parameters in instances()) {
+        let lattice = StableLattice::new(&parameters.applicants, &parameters.programs, usize::MAX).unwrap();
+        let found: Vec<_> = lattice.matchings().collect();
+        let stable = reference::stable_matchings(&parameters.applicants, &parameters.programs);
+        prop_assert!(lattice.is_complete());
+        prop_assert_eq!(found.len(), stable.len(), "found {:?}, expected {:?}", found, stable);
+        for m in found.iter() {
+            prop_assert!(stable.contains(m), "{:?} is not stable", m);
+        }
+    }
+
+    #[test]
+    fn lattice_selections_are_stable(parameters in instances()) {
+        let lattice = StableLattice::new(&parameters.applicants, &parameters.programs, usize::MAX).unwrap();
+        let stable = reference::stable_matchings(&parameters.applicants, &parameters.programs);
+
+        let mut matcher = Matcher::new();
+        matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+        prop_assert_eq!(lattice.applicant_optimal(), stability::assignment(&matcher));
+        prop_assert_eq!(Some(&lattice.applicant_optimal()), reference::applicant_optimal::<Applicant, Program>(&parameters.applicants, &stable));
+
+        for m in [lattice.program_optimal(), lattice.median().unwrap(), lattice.egalitarian()].iter() {
+            prop_assert!(stable.contains(m), "{:?} is not stable", m);
+        }
+        let least = stable.iter().map(|m| lattice.total_rank(m)).min().unwrap();
+        prop_assert_eq!(lattice.total_rank(&lattice.egalitarian()), least);
+
+        // the median of only some of the stable matchings is not reported
+        let limited = StableLattice::new(&parameters.applicants, &parameters.programs, 1).unwrap();
+        prop_assert_eq!(limited.is_complete(), stable.len() <= 1);
+        prop_assert_eq!(limited.median().is_some(), limited.is_complete());
+    }
+
+    #[test]
+    fn applicant_optimal_is_reached_from_any_stable_matching(parameters in instances()) {
+        let lattice = StableLattice::new(&parameters.applicants, &parameters.programs, usize::MAX).unwrap();
+        for m in lattice.matchings() {
+            let optimal = lattice::applicant_optimal_from(&parameters.applicants, &parameters.programs, &m).unwrap();
+            prop_assert_eq!(&optimal, &lattice.applicant_optimal(), "from {:?}", m);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use proptest::prelude::*;
+use residency_match::lifecycle;
+use residency_match::matcher::Matcher;
+use residency_match::models::MatchStatus;
+use residency_match::parameters::MatchParameters;
+use residency_match::stability;
+use common::match_parameters;
+
+/// Runs the parameters through the match from registration, returning where each applicant
+/// ended up. Applicants who didn't withdraw start over as registered.
+fn run(mut parameters: MatchParameters) -> MatchParameters {
+    for a in parameters.applicants.iter_mut().flat_map(|c| std::iter::once(&mut c.0).chain(c.1.as_mut())) {
+        if a.status != MatchStatus::Withdrawn {
+            a.status = MatchStatus::Registered;
+        }
+    }
+    lifecycle::record_applications(&mut parameters.applicants, &parameters.programs);
+    lifecycle::certify(&mut parameters.applicants);
+    let mut matcher = Matcher::new();
+    matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+    let assigned = stability::assignment(&matcher);
+    lifecycle::record_outcome(&mut parameters.applicants, &assigned);
+    parameters
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn statuses_follow_rank_lists_and_outcomes(parameters in match_parameters(10, 6, 0.3)) {
+        let mut matcher = Matcher::new();
+        matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+        let assigned = stability::assignment(&matcher);
+
+        let after = run(parameters.clone());
+        for a in after.applicants.iter().flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref())) {
+            let expected = match (a.ranking.is_empty(), assigned.contains_key(&a.id)) {
+                (true, _) => MatchStatus::Registered,
+                (false, true) => MatchStatus::Matched,
+                (false, false) => MatchStatus::Unmatched
+            };
+            prop_assert_eq!(a.status, expected, "applicant {}", a.id);
+        }
+
+        let statuses = lifecycle::by_status(&after.applicants);
+        prop_assert_eq!(statuses.values().map(|a| a.len()).sum::<usize>(), parameters.num_applicants);
+    }
+
+    #[test]
+    fn withdrawn_applicants_stay_out_of_the_match(parameters in match_parameters(10, 6, 0.3), pick in any::<prop::sample::Index>()) {
+        let mut withdrawn = parameters.clone();
+        let applicant = pick.index(parameters.num_applicants) as u32;
+        lifecycle::withdraw(&mut withdrawn, applicant);
+        prop_assert_eq!(withdrawn.num_applicants, parameters.num_applicants);
+
+        // a partner left on their own ranks each program of their half of the joint rank
+        // list once, where it first came up
+        let partner = parameters.applicants.iter().find_map(|c| match &c.1 {
+            Some(b) if c.0.id == applicant => Some(b),
+            Some(_) if c.1.as_ref().is_some_and(|b| b.id == applicant) => Some(&c.0),
+            _ => None
+        });
+        if let Some(partner) = partner {
+            let mut expected: Vec<u32> = Vec::new();
+            for p in partner.ranking.iter() {
+                if !expected.contains(p) {
+                    expected.push(*p);
+                }
+            }
+            let single = withdrawn.applicants.iter().find(|c| c.0.id == partner.id).unwrap();
+            prop_assert!(single.1.is_none() && single.0.couple.is_none());
+            prop_assert_eq!(&single.0.ranking, &expected);
+        }
+
+        let after = run(withdrawn);
+        let statuses = lifecycle::by_status(&after.applicants);
+        prop_assert_eq!(statuses.get(&MatchStatus::Withdrawn), Some(&vec![applicant]));
+        let a = after.applicants.iter().find(|c| c.0.id == applicant).unwrap();
+        prop_assert!(a.1.is_none() && a.0.ranking.is_empty());
+    }
+}
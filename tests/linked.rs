@@ -0,0 +1,67 @@
+mod common;
+
+use std::collections::HashMap;
+use proptest::prelude::*;
+use residency_match::matcher::Matcher;
+use residency_match::models::PositionType;
+use residency_match::stability::{self, BlockingPair};
+use common::linked_match_parameters;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn linked_placements_follow_supplemental_lists(parameters in linked_match_parameters(10, 6)) {
+        let mut matcher = Matcher::new();
+        matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+        let assigned = stability::assignment(&matcher);
+
+        for (program, matched) in matcher.matches.iter() {
+            prop_assert!(matched.len() <= program.capacity as usize,
+                         "program {} matched {} applicants with capacity {}", program.id, matched.len(), program.capacity);
+        }
+        let mut seen: HashMap<u32, usize> = HashMap::new();
+        for a in matcher.matches.iter().flat_map(|m| &m.1).chain(&matcher.unmatched_a) {
+            *seen.entry(a.id).or_insert(0) += 1;
+        }
+        for c in parameters.applicants.iter() {
+            let expected = if matcher.linked.contains_key(&c.0.id) { 2 } else { 1 };
+            prop_assert_eq!(seen.get(&c.0.id).copied(), Some(expected), "applicant {} is in the match the wrong number of times", c.0.id);
+        }
+
+        for (a, preliminary) in matcher.linked.iter() {
+            let applicant = &parameters.applicants.iter().find(|c| c.0.id == *a).unwrap().0;
+            let advanced = assigned[a];
+            let program = |id: u32| parameters.programs.iter().find(|p| p.id == id).unwrap();
+            prop_assert_eq!(program(advanced).position_type, PositionType::Advanced);
+            prop_assert!(applicant.supplemental[&advanced].contains(preliminary),
+                         "applicant {} holds preliminary program {}, which is not on their list for {}", a, preliminary, advanced);
+            prop_assert!(program(*preliminary).ranking.contains(a));
+        }
+
+        // like couples, linked positions can leave no stable result, which reprocessing finds
+        // out by going in circles
+        let blocking = stability::blocking_pairs(&parameters.applicants, &parameters.programs, &matcher);
+        prop_assert_eq!(&matcher.instability.blocking_pairs, &blocking);
+        prop_assert!(blocking.is_empty() || matcher.instability.repeated, "blocking pairs: {:?}", blocking);
+
+        // anyone at an advanced program who would rather have a preliminary program that would
+        // take them is reported
+        for c in parameters.applicants.iter() {
+            let a = &c.0;
+            let supplemental = match assigned.get(&a.id).and_then(|p| a.supplemental.get(p)) {
+                None => continue,
+                Some(s) => s
+            };
+            let current = matcher.linked.get(&a.id).and_then(|q| supplemental.iter().position(|r| r == q)).unwrap_or(supplemental.len());
+            for q in supplemental[..current].iter() {
+                let (program, held) = matcher.matches.iter().find(|m| m.0.id == *q).map(|m| (m.0, m.1.clone()))
+                    .unwrap_or_else(|| (matcher.unmatched_p.iter().find(|p| p.id == *q).unwrap(), Vec::new()));
+                let rank = |id: u32| program.ranking.iter().position(|r| *r == id);
+                let takes = rank(a.id).is_some_and(|r| program.capacity as usize > held.len() || held.iter().any(|h| rank(h.id).is_none_or(|w| w > r)));
+                prop_assert!(!takes || blocking.contains(&BlockingPair::Supplemental { applicant: a.id, program: *q }),
+                             "applicant {} would rather have preliminary program {}, which would take them", a.id, q);
+            }
+        }
+    }
+}
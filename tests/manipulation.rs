@@ -0,0 +1,38 @@
+mod common;
+
+use proptest::prelude::*;
+use residency_match::manipulation::{self, Manipulation, ManipulationPolicy};
+use residency_match::matcher::Matcher;
+use residency_match::stability;
+use common::match_parameters;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn single_applicants_cannot_gain_by_misreporting(parameters in match_parameters(10, 6, 0.0), seed in any::<u64>()) {
+        let policy = ManipulationPolicy { applicants: 10, programs: 0, attempts: 6 };
+        let outcomes = manipulation::analyze_manipulations(&parameters, &policy, seed).unwrap();
+        for o in outcomes.iter() {
+            let by_applicant = matches!(o.manipulation, Manipulation::Truncation { .. } | Manipulation::Swap { .. });
+            prop_assert!(by_applicant, "{:?} is not by an applicant", o.manipulation);
+            prop_assert!(!o.is_profitable(), "{:?} gained {}", o.manipulation, o.gain);
+        }
+    }
+
+    #[test]
+    fn reporting_truthfully_changes_nothing(parameters in match_parameters(10, 6, 0.3)) {
+        let mut matcher = Matcher::new();
+        matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+        let previous = stability::assignment(&matcher);
+
+        let truthful = parameters.applicants.iter()
+            .map(|c| Manipulation::Truncation { applicant: c.0.id, keep: c.0.ranking.len() })
+            .chain(parameters.programs.iter().map(|p| Manipulation::Withholding { program: p.id, withheld: 0 }));
+        for m in truthful {
+            let outcome = manipulation::try_manipulation(&parameters, &previous, m).unwrap();
+            prop_assert_eq!(outcome.gain, 0.0, "{:?}", m);
+            prop_assert_eq!(outcome.affected, 0, "{:?}", m);
+        }
+    }
+}
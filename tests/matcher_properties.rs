@@ -0,0 +1,129 @@
+mod common;
+
+use std::collections::HashMap;
+use proptest::prelude::*;
+use residency_match::matcher::{InstabilityResolution, Matcher};
+use residency_match::models::{Applicant, Couple, Program};
+use residency_match::parameters::MatchParameters;
+use residency_match::stability;
+use common::{applicant, match_parameters, program};
+
+fn run_match(parameters: &MatchParameters, resolution: InstabilityResolution) -> Matcher<'_, Applicant, Program> {
+    let mut matcher = Matcher::new();
+    matcher.run_match_(&parameters.applicants, &parameters.programs, resolution).unwrap();
+    matcher
+}
+
+fn resolutions() -> impl Strategy<Value = InstabilityResolution> {
+    prop_oneof![Just(InstabilityResolution::Report), Just(InstabilityResolution::Reprocess)]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn programs_stay_within_capacity(parameters in match_parameters(12, 6, 0.3), resolution in resolutions()) {
+        let matcher = run_match(&parameters, resolution);
+        for (program, matched) in matcher.matches.iter() {
+            prop_assert!(matched.len() <= program.capacity as usize,
+                         "program {} matched {} applicants with capacity {}", program.id, matched.len(), program.capacity);
+        }
+        matcher.unfilled_positions();
+    }
+
+    #[test]
+    fn applicants_match_at_most_once(parameters in match_parameters(12, 6, 0.3), resolution in resolutions()) {
+        let matcher = run_match(&parameters, resolution);
+        let mut seen: HashMap<u32, usize> = HashMap::new();
+        for a in matcher.matches.iter().flat_map(|m| &m.1).chain(&matcher.unmatched_a) {
+            *seen.entry(a.id).or_insert(0) += 1;
+        }
+        for c in parameters.applicants.iter() {
+            for a in std::iter::once(&c.0).chain(&c.1) {
+                prop_assert_eq!(seen.get(&a.id).copied(), Some(1), "applicant {} is not matched or unmatched exactly once", a.id);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_are_mutually_ranked(parameters in match_parameters(12, 6, 0.3), resolution in resolutions()) {
+        let matcher = run_match(&parameters, resolution);
+        for (program, matched) in matcher.matches.iter() {
+            for a in matched {
+                prop_assert!(a.ranking.contains(&program.id), "applicant {} did not rank program {}", a.id, program.id);
+                prop_assert!(program.ranking.contains(&a.id), "program {} did not rank applicant {}", program.id, a.id);
+            }
+        }
+    }
+
+    #[test]
+    fn couples_are_placed_together(parameters in match_parameters(12, 6, 0.5), resolution in resolutions()) {
+        let matcher = run_match(&parameters, resolution);
+        let assigned = stability::assignment(&matcher);
+        for c in parameters.applicants.iter() {
+            let b = match &c.1 {
+                None => continue,
+                Some(b) => b
+            };
+            match (assigned.get(&c.0.id), assigned.get(&b.id)) {
+                (None, None) => {},
+                (Some(p), Some(q)) => {
+                    let joint = c.0.ranking.iter().zip(b.ranking.iter());
+                    prop_assert!(joint.into_iter().any(|(r0, r1)| (r0, r1) == (p, q)),
+                                 "couple ({}, {}) matched to ({}, {}), which is not on their rank list", c.0.id, b.id, p, q);
+                },
+                (p, q) => prop_assert!(false, "couple ({}, {}) matched to ({:?}, {:?})", c.0.id, b.id, p, q)
+            }
+        }
+    }
+
+    // with couples a stable match need not exist, and the matcher does not revisit a couple's
+    // earlier choices when the applicants that blocked them are displaced, so only matches
+    // without couples are guaranteed to be stable
+    #[test]
+    fn singles_match_is_stable(parameters in match_parameters(12, 6, 0.0)) {
+        let matcher = run_match(&parameters, InstabilityResolution::Report);
+        let blocking = stability::blocking_pairs(&parameters.applicants, &parameters.programs, &matcher);
+        prop_assert!(blocking.is_empty(), "blocking pairs: {:?}", blocking);
+    }
+
+    #[test]
+    fn instability_is_reported(parameters in match_parameters(12, 6, 0.3), resolution in resolutions()) {
+        let matcher = run_match(&parameters, resolution);
+        let blocking = stability::blocking_pairs(&parameters.applicants, &parameters.programs, &matcher);
+        prop_assert_eq!(&matcher.instability.blocking_pairs, &blocking);
+        let unmatched: Vec<u32> = matcher.unmatched_a.iter().map(|a| a.id).collect();
+        for (a, b) in matcher.instability.cycling_couples.iter() {
+            prop_assert!(unmatched.contains(a) && unmatched.contains(b), "cycling couple ({}, {}) was matched", a, b);
+        }
+    }
+}
+
+#[test]
+fn reprocessing_keeps_going_after_a_result_comes_up_again() {
+    // putting the blocking applicants back in the order they applied goes in circles here,
+    // but putting them back in the reverse order reaches a stable match
+    let couple = |a: u32, b: u32, first: Vec<u32>, second: Vec<u32>| Couple(applicant(a, Some(b), first), Some(applicant(b, Some(a), second)));
+    let applicants = vec![
+        couple(0, 1, vec![2, 2, 0, 0, 0, 3], vec![0, 2, 3, 1, 0, 1]),
+        couple(2, 3, vec![], vec![]),
+        couple(4, 5, vec![3, 1, 1, 0], vec![0, 1, 2, 1]),
+        couple(6, 7, vec![3, 0, 0, 3, 2], vec![1, 1, 2, 3, 3]),
+        Couple(applicant(8, None, vec![1, 2]), None),
+        couple(9, 10, vec![3, 3, 1, 0], vec![1, 3, 2, 0]),
+    ];
+    let programs = vec![
+        program(0, 3, vec![1, 8, 5, 6, 0, 9, 3, 10, 4, 2]),
+        program(1, 1, vec![5, 8, 7, 4, 10, 0, 6, 2, 1, 3]),
+        program(2, 2, vec![]),
+        program(3, 1, vec![5, 3, 2, 1]),
+    ];
+    let parameters = MatchParameters { num_applicants: 11, num_programs: programs.len(), applicants, programs };
+
+    let reported = run_match(&parameters, InstabilityResolution::Report);
+    assert!(!reported.instability.is_stable());
+    let reprocessed = run_match(&parameters, InstabilityResolution::Reprocess);
+    assert!(reprocessed.instability.is_stable(), "blocking pairs: {:?}", reprocessed.instability.blocking_pairs);
+    assert!(reprocessed.instability.rounds >= 2, "stable after {} rounds", reprocessed.instability.rounds);
+    assert!(!reprocessed.instability.repeated);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use proptest::prelude::*;
+use residency_match::matcher::Matcher;
+use residency_match::outcome::{self, Direction, MatchOutcome};
+use residency_match::parameters::MatchParameters;
+use residency_match::scenario::{Scenario, Transformation};
+use residency_match::stability;
+use common::match_parameters;
+
+fn outcome_file() -> std::path::PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!("residency_match_outcome_{}_{}.bin", std::process::id(), NEXT.fetch_add(1, Ordering::SeqCst)))
+}
+
+fn run(parameters: &MatchParameters) -> MatchOutcome {
+    let mut matcher = Matcher::new();
+    matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+    MatchOutcome::new(parameters, &stability::assignment(&matcher))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn an_outcome_does_not_differ_from_itself(parameters in match_parameters(10, 6, 0.3)) {
+        let outcome = run(&parameters);
+        let diff = outcome::diff(&outcome, &outcome);
+        prop_assert!(diff.is_empty(), "{:?}", diff);
+        prop_assert_eq!(diff.matched_before, diff.matched_after);
+    }
+
+    #[test]
+    fn outcomes_survive_a_round_trip(parameters in match_parameters(10, 6, 0.3)) {
+        let outcome = run(&parameters);
+        let path = outcome_file();
+        let path = path.to_str().unwrap();
+        outcome.save(path).unwrap();
+        let opened = MatchOutcome::open(path);
+        std::fs::remove_file(path).unwrap();
+        prop_assert_eq!(opened.unwrap(), outcome);
+    }
+
+    #[test]
+    fn diffs_agree_with_the_outcomes(parameters in match_parameters(10, 6, 0.3), change in -1..=1i32) {
+        let mut changed = parameters.clone();
+        Scenario { transformations: vec![Transformation::Capacity(change)] }.apply(&mut changed, 0);
+        let (before, after) = (run(&parameters), run(&changed));
+        let diff = outcome::diff(&before, &after);
+
+        prop_assert!(diff.only_before.is_empty() && diff.only_after.is_empty());
+        for c in diff.changes.iter() {
+            let ranking = &before.rankings[&c.applicant];
+            let place = |p: Option<u32>| p.and_then(|p| ranking.iter().position(|q| *q == p)).unwrap_or(ranking.len()) as i64;
+            prop_assert_eq!(c.places, place(c.before) - place(c.after));
+            let direction = match c.places {
+                0 => Direction::Same,
+                n if n > 0 => Direction::Better,
+                _ => Direction::Worse
+            };
+            prop_assert_eq!(c.direction, direction);
+        }
+        prop_assert_eq!(diff.matched_after as i64 - diff.matched_before as i64,
+                        diff.newly_matched() as i64 - diff.newly_unmatched() as i64);
+        let filled: i64 = diff.fills.iter().map(|f| f.filled_after as i64 - f.filled_before as i64).sum();
+        prop_assert_eq!(filled, diff.matched_after as i64 - diff.matched_before as i64);
+        prop_assert_eq!(diff.changes.len(), diff.count(Direction::Better) + diff.count(Direction::Worse) + diff.count(Direction::Same));
+    }
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use residency_match::driver;
+use residency_match::models::{generator, Applicant, Couple, Program};
+use residency_match::ranker::{self, RankDistribution, RankStrategy};
+use common::{applicant, program};
+
+/// `count` programs of the given competitiveness, numbered from `first`.
+fn programs(first: u32, count: u32, competitiveness: f32) -> Vec<Program> {
+    (first..first + count).map(|id| Program { competitiveness, ..program(id, 1, Vec::new()) }).collect()
+}
+
+#[test]
+fn distributions_a_little_over_1_split_every_application_once() {
+    // 0.3005 + 0.5 + 0.2 is within tolerance of 1, but 2000 applications split by it
+    // unnormalised come to 601 + 1000 + 400
+    let distribution = RankDistribution::new(0.3005, 0.5, 0.2).unwrap();
+    let strategy = RankStrategy::new(1.05, 0.95, 0.90).unwrap();
+    let mut a = applicant(0, None, Vec::new());
+    a.applications = 2000;
+    // reach, realistic and safety programs for an applicant of competitiveness 0.5
+    let programs: Vec<Program> = programs(0, 601, 0.5).into_iter()
+        .chain(programs(601, 1000, 0.46))
+        .chain(programs(1601, 400, 0.3))
+        .collect();
+
+    for fallback in [false, true] {
+        let plan = ranker::plan(&Couple(a.clone(), None), &programs, &strategy, &distribution, fallback);
+        assert_eq!(plan.applications.0.len() + plan.shortfall, 2000);
+        // there are enough programs between the bands to make up for rounding
+        assert!(!fallback || plan.shortfall == 0, "{} applications could not be made", plan.shortfall);
+    }
+}
+
+#[test]
+fn rankings_do_not_depend_on_the_number_of_threads() {
+    // ids keep counting up across populations, so rankings are compared by position
+    let rankings = |threads: usize| {
+        generator::seed(7);
+        let (mut applicants, mut programs) = driver::generate_population_pool_(500, 100, 0.1);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| driver::generate_rankings(&mut applicants, &mut programs));
+
+        let applicants: Vec<&Applicant> = applicants.iter().flat_map(|c| std::iter::once(&c.0).chain(&c.1)).collect();
+        let position = |ids: Vec<u32>| move |id: &u32| ids.iter().position(|i| i == id).unwrap();
+        let program = position(programs.iter().map(|p| p.id).collect());
+        let applicant = position(applicants.iter().map(|a| a.id).collect());
+        let applicant_rankings: Vec<Vec<usize>> = applicants.iter().map(|a| a.ranking.iter().map(&program).collect()).collect();
+        let program_rankings: Vec<Vec<usize>> = programs.iter().map(|p| p.ranking.iter().map(&applicant).collect()).collect();
+        (applicant_rankings, program_rankings)
+    };
+
+    let single = rankings(1);
+    assert!(single.0.iter().any(|r| !r.is_empty()) && single.1.iter().any(|r| !r.is_empty()));
+    // 0 is rayon's default number of threads, which may be 1 too
+    for threads in [0, 4] {
+        assert_eq!(single, rankings(threads), "rankings differ with {} threads", threads);
+    }
+}
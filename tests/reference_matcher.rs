@@ -0,0 +1,64 @@
+mod common;
+
+use std::collections::HashMap;
+use proptest::prelude::*;
+use residency_match::matcher::{InstabilityResolution, Matcher};
+use residency_match::models::{Applicant, Couple, Program};
+use residency_match::parameters::MatchParameters;
+use residency_match::reference;
+use residency_match::stability;
+use common::{applicant, match_parameters, program};
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn singles_match_is_applicant_optimal(parameters in match_parameters(5, 3, 0.0)) {
+        let mut matcher = Matcher::new();
+        matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+        let assigned = stability::assignment(&matcher);
+
+        let stable = reference::stable_matchings(&parameters.applicants, &parameters.programs);
+        prop_assert!(stable.contains(&assigned), "{:?} is not one of the stable matchings {:?}", assigned, stable);
+        let optimal = reference::applicant_optimal::<Applicant, Program>(&parameters.applicants, &stable);
+        prop_assert_eq!(Some(&assigned), optimal);
+    }
+
+    #[test]
+    fn couples_match_agrees_with_reference(
+        parameters in match_parameters(4, 3, 0.5),
+        resolution in prop_oneof![Just(InstabilityResolution::Report), Just(InstabilityResolution::Reprocess)]
+    ) {
+        let mut matcher = Matcher::new();
+        matcher.run_match_(&parameters.applicants, &parameters.programs, resolution).unwrap();
+        let assigned = stability::assignment(&matcher);
+
+        let stable = reference::stable_matchings(&parameters.applicants, &parameters.programs);
+        // a result is reported stable exactly when the reference found it
+        prop_assert_eq!(matcher.instability.is_stable(), stable.contains(&assigned),
+                        "{:?} with blocking pairs {:?}, stable matchings {:?}", assigned, matcher.instability.blocking_pairs, stable);
+    }
+}
+
+#[test]
+fn reprocessing_can_miss_a_stable_matching() {
+    // reprocessing is a heuristic: it keeps the couple in both programs, though 0 at P2
+    // and 4 at P1 is stable
+    let applicants = vec![
+        Couple(applicant(0, None, vec![2]), None),
+        Couple(applicant(1, Some(2), vec![1, 2]), Some(applicant(2, Some(1), vec![2, 1]))),
+        Couple(applicant(4, None, vec![1]), None),
+    ];
+    let programs = vec![program(1, 1, vec![1, 4, 2]), program(2, 1, vec![1, 0, 2])];
+    let parameters = MatchParameters { num_applicants: 4, num_programs: programs.len(), applicants, programs };
+
+    let mut matcher = Matcher::new();
+    matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess).unwrap();
+    let assigned = stability::assignment(&matcher);
+    assert_eq!(assigned, HashMap::from([(1, 1), (2, 2)]));
+    assert!(!matcher.instability.is_stable());
+
+    let stable = reference::stable_matchings(&parameters.applicants, &parameters.programs);
+    assert_eq!(stable, vec![HashMap::from([(0, 2), (4, 1)])]);
+}
+
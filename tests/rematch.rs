@@ -0,0 +1,109 @@
+mod common;
+
+use std::collections::HashMap;
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+use residency_match::matcher::{InstabilityResolution, Matcher};
+use residency_match::parameters::{Delta, MatchParameters};
+use residency_match::stability;
+use common::{complete_match_parameters, match_parameters};
+
+/// A random change to the match. Couples' rank lists are left alone, since a partner's half
+/// of a joint rank list can't change on its own.
+fn delta(parameters: &MatchParameters) -> BoxedStrategy<Delta> {
+    let applicants: Vec<u32> = parameters.applicants.iter()
+        .flat_map(|c| std::iter::once(c.0.id).chain(c.1.as_ref().map(|b| b.id)))
+        .collect();
+    let singles: Vec<u32> = parameters.applicants.iter().filter(|c| c.1.is_none()).map(|c| c.0.id).collect();
+    let programs: Vec<u32> = parameters.programs.iter().map(|p| p.id).collect();
+
+    let mut deltas = vec![
+        (prop::sample::select(programs.clone()), 0..=3u16)
+            .prop_map(|(program, capacity)| Delta::Capacity { program, capacity })
+            .boxed(),
+        prop::sample::select(applicants.clone())
+            .prop_map(|applicant| Delta::Withdrawal { applicant })
+            .boxed(),
+        (prop::sample::select(programs.clone()), subsequence(applicants.clone(), 0..=applicants.len()).prop_shuffle())
+            .prop_map(|(program, ranking)| Delta::ProgramRanking { program, ranking })
+            .boxed(),
+    ];
+    if !singles.is_empty() {
+        deltas.push((prop::sample::select(singles), subsequence(programs.clone(), 0..=programs.len()).prop_shuffle())
+            .prop_map(|(applicant, ranking)| Delta::ApplicantRanking { applicant, ranking })
+            .boxed());
+    }
+    prop::strategy::Union::new(deltas).boxed()
+}
+
+fn with_delta(parameters: impl Strategy<Value = MatchParameters>) -> impl Strategy<Value = (MatchParameters, Delta)> {
+    parameters.prop_flat_map(|parameters| {
+        let delta = delta(&parameters);
+        (Just(parameters), delta)
+    })
+}
+
+/// The outcome of a full match on `parameters`, and of re-matching it after `delta`
+/// alongside a full match on the changed parameters.
+fn rematch(parameters: &MatchParameters, delta: &Delta, resolution: InstabilityResolution) -> (stability::Assignment, stability::Assignment) {
+    let mut matcher = Matcher::new();
+    matcher.run_match_(&parameters.applicants, &parameters.programs, resolution).unwrap();
+    let previous = stability::assignment(&matcher);
+
+    let mut changed = parameters.clone();
+    changed.apply(delta);
+    let mut rematcher = Matcher::new();
+    rematcher.rematch_(&changed.applicants, &changed.programs, &previous, delta, resolution).unwrap();
+    let mut full = Matcher::new();
+    full.run_match_(&changed.applicants, &changed.programs, resolution).unwrap();
+    (stability::assignment(&rematcher), stability::assignment(&full))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn singles_rematch_equals_full_rerun((parameters, delta) in with_delta(match_parameters(12, 6, 0.0))) {
+        let (rematched, full) = rematch(&parameters, &delta, InstabilityResolution::Report);
+        prop_assert_eq!(rematched, full);
+    }
+
+    #[test]
+    fn complete_rematch_equals_full_rerun((parameters, delta) in with_delta(complete_match_parameters(3))) {
+        let (rematched, full) = rematch(&parameters, &delta, InstabilityResolution::Report);
+        prop_assert_eq!(rematched, full);
+    }
+
+    #[test]
+    fn couples_rematch_keeps_invariants((parameters, delta) in with_delta(match_parameters(12, 6, 0.3))) {
+        let mut matcher = Matcher::new();
+        matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess).unwrap();
+        let previous = stability::assignment(&matcher);
+        let mut changed = parameters.clone();
+        changed.apply(&delta);
+        let mut rematcher = Matcher::new();
+        rematcher.rematch_(&changed.applicants, &changed.programs, &previous, &delta, InstabilityResolution::Reprocess).unwrap();
+
+        for (program, matched) in rematcher.matches.iter() {
+            prop_assert!(matched.len() <= program.capacity as usize);
+            for a in matched {
+                prop_assert!(a.ranking.contains(&program.id) && program.ranking.contains(&a.id));
+            }
+        }
+        let mut seen: HashMap<u32, usize> = HashMap::new();
+        for a in rematcher.matches.iter().flat_map(|m| &m.1).chain(&rematcher.unmatched_a) {
+            *seen.entry(a.id).or_insert(0) += 1;
+        }
+        let assigned = stability::assignment(&rematcher);
+        for c in changed.applicants.iter() {
+            for a in std::iter::once(&c.0).chain(&c.1) {
+                prop_assert_eq!(seen.get(&a.id).copied(), Some(1), "applicant {} is not matched or unmatched exactly once", a.id);
+            }
+            if let Some(b) = &c.1 {
+                prop_assert_eq!(assigned.contains_key(&c.0.id), assigned.contains_key(&b.id));
+            }
+        }
+        let blocking = stability::blocking_pairs(&changed.applicants, &changed.programs, &rematcher);
+        prop_assert_eq!(&rematcher.instability.blocking_pairs, &blocking);
+    }
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use proptest::prelude::*;
+use residency_match::audit::{self, EventFile};
+use residency_match::matcher::{InstabilityResolution, Matcher};
+use residency_match::models::{Applicant, Program};
+use residency_match::parameters::MatchParameters;
+use residency_match::replay::{self, MatchState, Replayer};
+use residency_match::stability;
+use common::{linked_match_parameters, match_parameters};
+
+/// A path in the temporary directory no other test case writes to.
+fn event_file() -> std::path::PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!("residency_match_events_{}_{}.bin", std::process::id(), NEXT.fetch_add(1, Ordering::SeqCst)))
+}
+
+/// Where the match ended, as a replay should find it.
+fn final_state(matcher: &Matcher<Applicant, Program>) -> MatchState {
+    MatchState {
+        holds: matcher.matches.iter()
+            .map(|m| (m.0.id, m.1.iter().map(|a| a.id).collect::<BTreeSet<u32>>()))
+            .collect(),
+        linked: matcher.linked.iter().map(|(a, p)| (*a, *p)).collect(),
+        unmatched: matcher.unmatched_a.iter().map(|a| a.id).collect(),
+    }
+}
+
+fn parameters() -> impl Strategy<Value = MatchParameters> {
+    prop_oneof![match_parameters(10, 6, 0.3), linked_match_parameters(10, 6)]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn replaying_every_event_ends_where_the_match_did(parameters in parameters()) {
+        let mut matcher = Matcher::new();
+        matcher.record_events();
+        matcher.run_match_(&parameters.applicants, &parameters.programs, InstabilityResolution::Reprocess).unwrap();
+        let events = matcher.events.recorded().unwrap();
+
+        let state = replay::state_at(events, events.len());
+        prop_assert_eq!(&state, &final_state(&matcher));
+        prop_assert_eq!(state.assignment(), stability::assignment(&matcher).into_iter().collect::<BTreeMap<u32, u32>>());
+    }
+
+    #[test]
+    fn saved_events_replay_the_same(parameters in match_parameters(10, 6, 0.3), position in any::<prop::sample::Index>()) {
+        let path = event_file();
+        let mut recorded = Matcher::new();
+        recorded.record_events();
+        recorded.stream_events(EventFile::create(path.to_str().unwrap()).unwrap());
+        recorded.run_match(&parameters.applicants, &parameters.programs).unwrap();
+        recorded.finish_events().unwrap();
+        let saved = audit::read_events(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let events = recorded.events.recorded().unwrap();
+        prop_assert_eq!(&saved.unwrap(), events);
+
+        // seeking back and forth gets to the same place as stepping there
+        let position = position.index(events.len() + 1);
+        let mut replayer = Replayer::new(events);
+        let end = replayer.seek(events.len()).clone();
+        prop_assert_eq!(&end, &final_state(&recorded));
+        let back = replayer.seek(position).clone();
+        let mut stepped = Replayer::new(events);
+        while stepped.position() < position {
+            stepped.step();
+        }
+        prop_assert_eq!(&back, stepped.state());
+        prop_assert_eq!(replayer.position(), position);
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use std::collections::{HashMap, HashSet};
+use proptest::prelude::*;
+use residency_match::models::Applicant;
+use residency_match::parameters::MatchParameters;
+use residency_match::scenario::{self, Scenario, Transformation};
+use common::match_parameters;
+
+fn everyone(parameters: &MatchParameters) -> Vec<&Applicant> {
+    parameters.applicants.iter().flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref())).collect()
+}
+
+#[test]
+fn transformations_are_parsed() {
+    let scenario = Scenario::parse(&["couples=2", "capacity=+1", "capacity=-2", "applications=0.8"]).unwrap();
+    assert_eq!(scenario.transformations, vec![
+        Transformation::CoupleRate(2.0),
+        Transformation::Capacity(1),
+        Transformation::Capacity(-2),
+        Transformation::Applications(0.8),
+    ]);
+    for invalid in ["couples", "couples=-1", "capacity=x", "positions=1"] {
+        assert!(Scenario::parse(&[invalid]).is_err(), "{} was parsed", invalid);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn an_empty_scenario_changes_nothing(parameters in match_parameters(10, 6, 0.3)) {
+        let comparison = scenario::run_scenario(&parameters, &Scenario::default(), 0).unwrap();
+        prop_assert_eq!(comparison.changed, 0);
+        prop_assert_eq!(comparison.baseline_assigned, comparison.counterfactual_assigned);
+    }
+
+    #[test]
+    fn couple_rates_change_who_is_in_couples(parameters in match_parameters(10, 6, 0.3), factor in 0.0..3.0f64, seed in any::<u64>()) {
+        let mut changed = parameters.clone();
+        Scenario { transformations: vec![Transformation::CoupleRate(factor)] }.apply(&mut changed, seed);
+
+        let before: HashSet<u32> = everyone(&parameters).iter().map(|a| a.id).collect();
+        let after: HashSet<u32> = everyone(&changed).iter().map(|a| a.id).collect();
+        prop_assert_eq!(&before, &after);
+        let coupled = |p: &MatchParameters| p.applicants.iter().filter(|c| c.1.is_some()).count() * 2;
+        let wanted = usize::min((coupled(&parameters) as f64 * factor).round() as usize, after.len()) / 2 * 2;
+        // only as many couples as there are single applicants to pair up can be made
+        let singles = after.len() - coupled(&parameters);
+        prop_assert_eq!(coupled(&changed), usize::min(wanted, coupled(&parameters) + singles / 2 * 2));
+        for c in changed.applicants.iter() {
+            match &c.1 {
+                None => prop_assert_eq!(c.0.couple, None),
+                Some(b) => {
+                    prop_assert_eq!((c.0.couple, b.couple), (Some(b.id), Some(c.0.id)));
+                    prop_assert_eq!(c.0.ranking.len(), b.ranking.len());
+                }
+            }
+        }
+        let rankings = |p: &MatchParameters| p.programs.iter().map(|p| p.ranking.clone()).collect::<Vec<_>>();
+        prop_assert_eq!(rankings(&parameters), rankings(&changed));
+    }
+
+    #[test]
+    fn fewer_applications_drop_the_least_wanted_programs(parameters in match_parameters(10, 6, 0.3), factor in 0.0..1.0f64) {
+        let mut parameters = parameters;
+        for p in parameters.programs.iter_mut() {
+            p.tiers = p.ranking.chunks(2).map(|tier| tier.len() as u32).collect();
+        }
+        let mut changed = parameters.clone();
+        Scenario { transformations: vec![Transformation::Applications(factor)] }.apply(&mut changed, 0);
+
+        let original: HashMap<u32, &Applicant> = everyone(&parameters).into_iter().map(|a| (a.id, a)).collect();
+        for a in everyone(&changed) {
+            // what's left is the start of the original list, with pairs of a couple dropped together
+            let mut rest = original[&a.id].ranking.iter();
+            prop_assert!(a.ranking.iter().all(|p| rest.any(|q| q == p)), "applicant {} reordered their list", a.id);
+            let mut seen = HashSet::new();
+            let programs: Vec<u32> = original[&a.id].ranking.iter().copied().filter(|p| seen.insert(*p)).collect();
+            let keep = (programs.len() as f64 * factor).round() as usize;
+            for p in programs.iter().skip(keep) {
+                let program = changed.programs.iter().find(|q| q.id == *p).unwrap();
+                prop_assert!(!program.ranking.contains(&a.id), "program {} still ranks applicant {}", p, a.id);
+            }
+        }
+        for p in changed.programs.iter() {
+            prop_assert_eq!(p.tiers.iter().sum::<u32>() as usize, p.ranking.len());
+            prop_assert!(p.tiers.iter().all(|size| *size > 0));
+        }
+    }
+}
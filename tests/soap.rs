@@ -0,0 +1,53 @@
+mod common;
+
+use std::collections::HashMap;
+use proptest::prelude::*;
+use residency_match::matcher::Matcher;
+use residency_match::soap::{self, SoapPolicy};
+use common::match_parameters;
+
+fn policies() -> impl Strategy<Value = SoapPolicy> {
+    (1..=6usize, 1..=4usize).prop_map(|(applications, rounds)| SoapPolicy { applications, rounds })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn offers_only_fill_open_positions(parameters in match_parameters(12, 6, 0.3), policy in policies()) {
+        let mut matcher = Matcher::new();
+        matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+        let outcome = soap::run_soap_(&matcher, &policy);
+
+        let unmatched: Vec<u32> = matcher.unmatched_a.iter().map(|a| a.id).collect();
+        let mut filled: HashMap<u32, usize> = HashMap::new();
+        for (a, p) in outcome.placed.iter() {
+            prop_assert!(unmatched.contains(a), "applicant {} was placed but had matched", a);
+            *filled.entry(*p).or_insert(0) += 1;
+        }
+        for (p, filled) in filled {
+            let program = parameters.programs.iter().find(|q| q.id == p).unwrap();
+            let matched = matcher.matches.iter().find(|m| m.0.id == p).map_or(0, |m| m.1.len());
+            prop_assert!(matched + filled <= program.capacity as usize,
+                         "program {} filled {} positions after matching {} with capacity {}", p, filled, matched, program.capacity);
+        }
+
+        prop_assert!(outcome.rounds.len() <= policy.rounds);
+        let accepted: usize = outcome.rounds.iter().map(|r| r.accepted).sum();
+        prop_assert_eq!(accepted, outcome.placed.len());
+        for round in outcome.rounds.iter() {
+            prop_assert_eq!(round.offers, round.accepted + round.declined);
+        }
+        prop_assert_eq!(outcome.unfilled as usize, matcher.unfilled_positions() as usize - outcome.placed.len());
+    }
+
+    #[test]
+    fn last_round_offers_are_accepted(parameters in match_parameters(12, 6, 0.3), applications in 1..=6usize) {
+        let mut matcher = Matcher::new();
+        matcher.run_match(&parameters.applicants, &parameters.programs).unwrap();
+        let outcome = soap::run_soap_(&matcher, &SoapPolicy { applications, rounds: 1 });
+        for round in outcome.rounds.iter() {
+            prop_assert_eq!(round.waiting, 0);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::collections::HashSet;
+use proptest::prelude::*;
+use residency_match::parameters::MatchParameters;
+use residency_match::ties::{self, TieBreaking};
+use common::match_parameters;
+
+/// Parameters where programs rank in tiers of random sizes.
+fn tied_parameters() -> impl Strategy<Value = MatchParameters> {
+    match_parameters(10, 6, 0.3)
+        .prop_flat_map(|parameters| {
+            let cuts: Vec<_> = parameters.programs.iter()
+                .map(|p| prop::collection::vec(prop::bool::ANY, p.ranking.len()))
+                .collect();
+            (Just(parameters), cuts)
+        })
+        .prop_map(|(mut parameters, cuts)| {
+            for (p, cuts) in parameters.programs.iter_mut().zip(cuts) {
+                // a new tier starts wherever there is a cut
+                for (i, cut) in cuts.into_iter().enumerate() {
+                    match p.tiers.last_mut() {
+                        Some(size) if !cut || i == 0 => *size += 1,
+                        _ => p.tiers.push(1)
+                    }
+                }
+            }
+            parameters
+        })
+}
+
+fn rules() -> impl Strategy<Value = TieBreaking> {
+    prop::sample::select(TieBreaking::ALL.to_vec())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn ties_are_only_reordered_within_tiers(parameters in tied_parameters(), rule in rules(), seed in any::<u64>()) {
+        let mut broken = parameters.clone();
+        ties::break_ties(&mut broken, rule, seed);
+        for (before, after) in parameters.programs.iter().zip(broken.programs.iter()) {
+            prop_assert_eq!(&before.tiers, &after.tiers);
+            for (t0, t1) in ties::tiers(before).into_iter().zip(ties::tiers(after)) {
+                prop_assert_eq!(t0.iter().collect::<HashSet<_>>(), t1.iter().collect::<HashSet<_>>());
+            }
+        }
+
+        let mut again = parameters.clone();
+        ties::break_ties(&mut again, rule, seed);
+        let rankings = |p: &MatchParameters| p.programs.iter().map(|p| p.ranking.clone()).collect::<Vec<_>>();
+        prop_assert_eq!(rankings(&again), rankings(&broken));
+    }
+
+    #[test]
+    fn single_lottery_orders_every_tie_the_same_way(parameters in tied_parameters(), seed in any::<u64>()) {
+        let mut broken = parameters.clone();
+        ties::break_ties(&mut broken, TieBreaking::SingleLottery, seed);
+        // who goes ahead of whom when tied, from every program
+        let mut ahead: HashSet<(u32, u32)> = HashSet::new();
+        for p in broken.programs.iter() {
+            for tier in ties::tiers(p) {
+                for (i, a) in tier.iter().enumerate() {
+                    for b in tier[i + 1..].iter() {
+                        prop_assert!(!ahead.contains(&(*b, *a)), "applicants {} and {} are ordered both ways", a, b);
+                        ahead.insert((*a, *b));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn applicant_priority_favours_higher_rankings(parameters in tied_parameters(), seed in any::<u64>()) {
+        let mut broken = parameters.clone();
+        ties::break_ties(&mut broken, TieBreaking::ApplicantPriority, seed);
+        let choice = |a: u32, p: u32| broken.applicants.iter()
+            .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+            .find(|b| b.id == a)
+            .and_then(|b| b.ranking.iter().position(|q| *q == p))
+            .unwrap_or(usize::MAX);
+        for p in broken.programs.iter() {
+            for tier in ties::tiers(p) {
+                let choices: Vec<usize> = tier.iter().map(|a| choice(*a, p.id)).collect();
+                prop_assert!(choices.windows(2).all(|w| w[0] <= w[1]), "program {} tier {:?} at choices {:?}", p.id, tier, choices);
+            }
+        }
+    }
+
+    #[test]
+    fn comparison_covers_every_rule(parameters in tied_parameters(), seed in any::<u64>()) {
+        let outcomes = ties::compare_tie_breaking(&parameters, seed).unwrap();
+        prop_assert_eq!(outcomes.iter().map(|o| o.rule).collect::<Vec<_>>(), TieBreaking::ALL.to_vec());
+        prop_assert_eq!(outcomes[0].changed, 0);
+        for o in outcomes.iter() {
+            prop_assert_eq!(o.matched, o.assigned.len());
+            prop_assert!(o.first_choice <= o.matched);
+        }
+    }
+}
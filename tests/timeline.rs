@@ -0,0 +1,78 @@
+mod common;
+
+use chrono::{DateTime, Duration};
+use proptest::prelude::*;
+use residency_match::matcher::Matcher;
+use residency_match::parameters::MatchParameters;
+use residency_match::timeline;
+use common::match_parameters;
+
+/// Parameters where each program certified its rank list some hours before or after its
+/// deadline, or not at all.
+fn certified_parameters() -> impl Strategy<Value = MatchParameters> {
+    match_parameters(10, 6, 0.3)
+        .prop_flat_map(|parameters| {
+            let certified = prop::collection::vec(prop::option::of(-48..=48i64), parameters.programs.len());
+            (Just(parameters), certified)
+        })
+        .prop_map(|(mut parameters, certified)| {
+            for (p, hours) in parameters.programs.iter_mut().zip(certified) {
+                p.deadline = DateTime::default() + Duration::days(30);
+                p.certified = hours.map(|h| p.deadline + Duration::hours(h));
+            }
+            parameters
+        })
+}
+
+/// Whether `a` is `b` with some entries left out.
+fn is_subsequence<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    let mut b = b.iter();
+    a.iter().all(|x| b.any(|y| y == x))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn late_programs_are_removed_from_every_rank_list(parameters in certified_parameters()) {
+        let mut excluded_parameters = parameters.clone();
+        let excluded = timeline::exclude_late(&mut excluded_parameters);
+
+        prop_assert_eq!(excluded.len() + excluded_parameters.programs.len(), parameters.programs.len());
+        prop_assert_eq!(excluded_parameters.num_programs, excluded_parameters.programs.len());
+        for p in parameters.programs.iter() {
+            let late = excluded.iter().any(|e| e.program == p.id);
+            prop_assert_eq!(late, !timeline::is_certified(p), "program {} certified {:?} for {}", p.id, p.certified, p.deadline);
+        }
+
+        let is_late = |p: &u32| excluded.iter().any(|e| e.program == *p);
+        for (before, after) in parameters.applicants.iter().zip(excluded_parameters.applicants.iter()) {
+            match (&before.1, &after.1) {
+                (None, None) => {
+                    let kept: Vec<u32> = before.0.ranking.iter().copied().filter(|p| !is_late(p)).collect();
+                    prop_assert_eq!(&after.0.ranking, &kept);
+                },
+                (Some(b_before), Some(b_after)) => {
+                    prop_assert_eq!(after.0.ranking.len(), b_after.ranking.len());
+                    let joint = |a: &[u32], b: &[u32]| a.iter().copied().zip(b.iter().copied()).collect::<Vec<_>>();
+                    let kept = joint(&after.0.ranking, &b_after.ranking);
+                    prop_assert!(kept.iter().all(|(p, q)| !is_late(p) && !is_late(q)));
+                    prop_assert!(is_subsequence(&kept, &joint(&before.0.ranking, &b_before.ranking)));
+                },
+                _ => prop_assert!(false, "applicant {} changed couples", before.0.id)
+            }
+        }
+
+        for e in excluded.iter() {
+            let ranked_by: Vec<u32> = parameters.applicants.iter()
+                .flat_map(|c| std::iter::once(&c.0).chain(c.1.as_ref()))
+                .filter(|a| a.ranking.contains(&e.program))
+                .map(|a| a.id)
+                .collect();
+            prop_assert_eq!(&e.ranked_by, &ranked_by);
+        }
+
+        let mut matcher = Matcher::new();
+        prop_assert!(matcher.run_match(&excluded_parameters.applicants, &excluded_parameters.programs).is_ok());
+    }
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::collections::{HashMap, HashSet};
+use proptest::prelude::*;
+use residency_match::matcher::Matcher;
+use residency_match::tracks;
+use common::tracked_match_parameters;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn reversions_only_move_positions_within_programs(parameters in tracked_match_parameters(10, 6, 0.3)) {
+        let mut tracks = tracks::track_programs(&parameters.programs);
+        let rules = tracks::reversion_rules(&parameters.programs);
+        let before: HashMap<u32, u16> = tracks.iter().map(|t| (t.id, t.capacity)).collect();
+        let reversions = tracks::revert_unfilled(&parameters.applicants, &mut tracks, &rules).unwrap();
+
+        let mut reverted = HashSet::new();
+        for r in reversions.iter() {
+            prop_assert_eq!(rules.get(&r.from), Some(&r.to), "positions moved from track {} against the rules", r.from);
+            prop_assert!(r.positions > 0);
+            prop_assert!(reverted.insert(r.from), "track {} reverted twice", r.from);
+        }
+        for program in parameters.programs.iter() {
+            let capacity = |capacities: &dyn Fn(u32) -> u16| program.tracks.iter().map(|t| capacities(t.id) as u32).sum::<u32>();
+            let after = |id: u32| tracks.iter().find(|t| t.id == id).unwrap().capacity;
+            prop_assert_eq!(capacity(&|id| before[&id]), capacity(&after), "program {} lost or gained positions", program.id);
+        }
+    }
+
+    #[test]
+    fn tracks_that_can_revert_end_filled(parameters in tracked_match_parameters(10, 6, 0.0)) {
+        let mut tracks = tracks::track_programs(&parameters.programs);
+        let rules = tracks::reversion_rules(&parameters.programs);
+        let reversions = tracks::revert_unfilled(&parameters.applicants, &mut tracks, &rules).unwrap();
+        let reverted: HashSet<u32> = reversions.iter().map(|r| r.from).collect();
+
+        let mut matcher = Matcher::new();
+        matcher.run_match(&parameters.applicants, &tracks).unwrap();
+        for t in tracks.iter().filter(|t| rules.contains_key(&t.id) && !reverted.contains(&t.id)) {
+            let filled = matcher.matches.iter().find(|m| m.0.id == t.id).map_or(0, |m| m.1.len());
+            prop_assert_eq!(filled, t.capacity as usize, "track {} kept unfilled positions it could revert", t.id);
+        }
+    }
+}